@@ -0,0 +1,170 @@
+use crate::render::text_of;
+use crate::tag::{escape_attribute, Tag};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// A change to document metadata found in `<head>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadChange {
+    /// Element the change was found on: `title`, `meta`, `link` or `script`
+    pub element: String,
+    /// Identifies the item, e.g. the meta `name` or the link `rel`
+    pub key: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// A full HTML document split around its `<body>` content
+#[derive(Debug)]
+pub(crate) struct Document<'a> {
+    /// Everything up to and including the `<body>` start tag
+    pub prefix: &'a str,
+    pub head: &'a str,
+    pub body: &'a str,
+    /// The `</body>` end tag and everything after it
+    pub suffix: &'a str,
+}
+
+impl<'a> Document<'a> {
+    /// Splits a full document, returning `None` for fragments without `<body>`
+    pub fn split(html: &'a str) -> Option<Self> {
+        static BODY_START: OnceLock<Regex> = OnceLock::new();
+        static BODY_END: OnceLock<Regex> = OnceLock::new();
+        static HEAD: OnceLock<Regex> = OnceLock::new();
+
+        let body_start = BODY_START.get_or_init(|| Regex::new(r"(?i)<body(\s[^>]*)?>").unwrap());
+        let body_end = BODY_END.get_or_init(|| Regex::new(r"(?i)</body\s*>").unwrap());
+        let head =
+            HEAD.get_or_init(|| Regex::new(r"(?is)<head(\s[^>]*)?>(.*?)</head\s*>").unwrap());
+
+        let start = body_start.find(html)?;
+        let end = body_end
+            .find_iter(&html[start.end()..])
+            .last()
+            .map(|m| start.end() + m.start())
+            .unwrap_or(html.len());
+        let head = head
+            .captures(&html[..start.start()])
+            .and_then(|caps| caps.get(2))
+            .map(|m| m.as_str())
+            .unwrap_or("");
+
+        Some(Self {
+            prefix: &html[..start.end()],
+            head,
+            body: &html[start.end()..end],
+            suffix: &html[end..],
+        })
+    }
+}
+
+/// Compares the title, meta, link and script items of two `<head>` sections
+pub(crate) fn head_changes(before: &str, after: &str) -> Vec<HeadChange> {
+    let before_items = head_items(before);
+    let after_items = head_items(after);
+    let mut changes = Vec::new();
+
+    for (element, key, value) in &before_items {
+        let after_value = after_items
+            .iter()
+            .find(|(e, k, _)| e == element && k == key)
+            .map(|(_, _, v)| v);
+        if after_value != Some(value) {
+            changes.push(HeadChange {
+                element: element.clone(),
+                key: key.clone(),
+                before: Some(value.clone()),
+                after: after_value.cloned(),
+            });
+        }
+    }
+    for (element, key, value) in &after_items {
+        if !before_items
+            .iter()
+            .any(|(e, k, _)| e == element && k == key)
+        {
+            changes.push(HeadChange {
+                element: element.clone(),
+                key: key.clone(),
+                before: None,
+                after: Some(value.clone()),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Renders head changes as a summary section placed at the top of the body
+pub(crate) fn render_summary(changes: &[HeadChange]) -> String {
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut summary = String::from("<section class=\"diff-summary\"><h2>Document changes</h2><ul>");
+    for change in changes {
+        summary.push_str(&format!(
+            "<li><code>{}</code> {}: ",
+            change.element,
+            escape_attribute(&change.key)
+        ));
+        if let Some(before) = &change.before {
+            summary.push_str(&format!("<del>{}</del>", escape_attribute(before)));
+        }
+        if let Some(after) = &change.after {
+            summary.push_str(&format!("<ins>{}</ins>", escape_attribute(after)));
+        }
+        summary.push_str("</li>");
+    }
+    summary.push_str("</ul></section>");
+    summary
+}
+
+/// Extracts `(element, key, value)` triples from a head section. Links and
+/// scripts are keyed by their URL so that added and removed resources are
+/// reported individually.
+fn head_items(head: &str) -> Vec<(String, String, String)> {
+    static TITLE: OnceLock<Regex> = OnceLock::new();
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+
+    let title = TITLE.get_or_init(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title\s*>").unwrap());
+    let tags = TAGS.get_or_init(|| Regex::new(r"(?i)<(meta|link|script)(\s[^>]*)?>").unwrap());
+
+    let mut items = Vec::new();
+    if let Some(caps) = title.captures(head) {
+        items.push((
+            "title".to_string(),
+            "title".to_string(),
+            text_of(&caps[1]).trim().to_string(),
+        ));
+    }
+
+    for m in tags.find_iter(head) {
+        let Some(tag) = Tag::parse(m.as_str()) else {
+            continue;
+        };
+        let item = match tag.name.as_str() {
+            "meta" => ["name", "property", "http-equiv"]
+                .iter()
+                .find_map(|key| tag.attribute(key))
+                .map(|key| (key.to_string(), tag.attribute("content").unwrap_or("")))
+                .or_else(|| {
+                    tag.attribute("charset")
+                        .map(|charset| ("charset".to_string(), charset))
+                }),
+            "link" => tag.attribute("href").map(|href| {
+                (
+                    format!("{} {}", tag.attribute("rel").unwrap_or(""), href),
+                    href,
+                )
+            }),
+            "script" => tag.attribute("src").map(|src| (src.to_string(), src)),
+            _ => None,
+        };
+        if let Some((key, value)) = item {
+            items.push((tag.name.clone(), key, value.to_string()));
+        }
+    }
+
+    items
+}
@@ -0,0 +1,172 @@
+use crate::error::DiffError;
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Character encoding of raw input bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Charset {
+    #[default]
+    Utf8,
+    /// ISO-8859-1, decoded as Windows-1252 like browsers do
+    Latin1,
+    /// UTF-16, little endian unless a byte order mark says otherwise
+    Utf16,
+    /// Detected from a byte order mark or `<meta charset>`, falling back to
+    /// UTF-8 and then Windows-1252
+    Auto,
+}
+
+/// Windows-1252 characters for bytes 0x80 to 0x9F, which Latin-1 leaves
+/// as control characters
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// Encodes a string into raw bytes in the given charset. Latin-1 writes
+/// characters outside Windows-1252 as numeric character references, and
+/// UTF-16 is little endian with a byte order mark. `Auto` encodes UTF-8.
+pub fn encode_string(text: &str, charset: Charset) -> Vec<u8> {
+    match charset {
+        Charset::Utf8 | Charset::Auto => text.as_bytes().to_vec(),
+        Charset::Latin1 => encode_windows_1252(text),
+        Charset::Utf16 => [0xFF, 0xFE]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect(),
+    }
+}
+
+/// Decodes raw bytes into a string using the given charset. `field` names
+/// the input in the error returned for undecodable bytes.
+pub fn decode_bytes(bytes: &[u8], charset: Charset, field: &str) -> Result<String, DiffError> {
+    let invalid = |message: String| DiffError::InvalidEncoding {
+        field: field.to_string(),
+        message,
+    };
+
+    match charset {
+        Charset::Utf8 => {
+            let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+            String::from_utf8(bytes.to_vec()).map_err(|e| invalid(e.to_string()))
+        }
+        Charset::Latin1 => Ok(decode_windows_1252(bytes)),
+        Charset::Utf16 => decode_utf16(bytes).map_err(invalid),
+        Charset::Auto => decode_bytes(bytes, detect_charset(bytes), field),
+    }
+}
+
+/// Guesses the charset of raw bytes from a byte order mark, a
+/// `<meta charset>` declaration or whether the bytes are valid UTF-8
+pub fn detect_charset(bytes: &[u8]) -> Charset {
+    if bytes.starts_with(b"\xEF\xBB\xBF") {
+        return Charset::Utf8;
+    }
+    if bytes.starts_with(b"\xFF\xFE") || bytes.starts_with(b"\xFE\xFF") {
+        return Charset::Utf16;
+    }
+    // UTF-16 without a byte order mark is not ASCII compatible, so it has to
+    // be recognised before looking for a declaration
+    if utf16_big_endian(bytes).is_some() {
+        return Charset::Utf16;
+    }
+
+    static META: OnceLock<Regex> = OnceLock::new();
+    let meta = META.get_or_init(|| {
+        Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?([A-Za-z0-9_-]+)"#).unwrap()
+    });
+    // Declarations must appear early in the document
+    let head = &bytes[..bytes.len().min(1024)];
+    if let Some(caps) = meta.captures(head) {
+        match caps[1].to_ascii_lowercase().as_slice() {
+            b"utf-8" | b"utf8" => return Charset::Utf8,
+            b"iso-8859-1" | b"latin1" | b"windows-1252" | b"cp1252" | b"us-ascii" => {
+                return Charset::Latin1
+            }
+            b"utf-16" | b"utf-16le" | b"utf-16be" => return Charset::Utf16,
+            _ => {}
+        }
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        Charset::Utf8
+    } else {
+        Charset::Latin1
+    }
+}
+
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9F => WINDOWS_1252[(b - 0x80) as usize],
+            _ => b as char,
+        })
+        .collect()
+}
+
+fn encode_windows_1252(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match WINDOWS_1252.iter().position(|&w| w == c) {
+            Some(i) if !matches!(c, '\u{80}'..='\u{9f}') => bytes.push(0x80 + i as u8),
+            _ if (c as u32) < 0x80 || matches!(c, '\u{a0}'..='\u{ff}') => bytes.push(c as u8),
+            _ => bytes.extend(format!("&#{};", c as u32).into_bytes()),
+        }
+    }
+    bytes
+}
+
+/// Recognises UTF-16 without a byte order mark by the zero high bytes of
+/// ASCII markup, returning whether it is big endian
+fn utf16_big_endian(bytes: &[u8]) -> Option<bool> {
+    let sample = &bytes[..bytes.len().min(1024) & !1];
+    if sample.is_empty() {
+        return None;
+    }
+    let pairs = sample.len() / 2;
+    let zeros_at = |offset: usize| {
+        sample
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    // Markup is mostly ASCII, so most code units have a zero high byte
+    if odd * 2 > pairs && even == 0 {
+        Some(false)
+    } else if even * 2 > pairs && odd == 0 {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+fn decode_utf16(bytes: &[u8]) -> Result<String, String> {
+    let (bytes, big_endian) = if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        (rest, true)
+    } else if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        (rest, false)
+    } else {
+        (bytes, utf16_big_endian(bytes).unwrap_or(false))
+    };
+    if bytes.len() % 2 != 0 {
+        return Err("UTF-16 input has an odd number of bytes".to_string());
+    }
+
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            if big_endian {
+                u16::from_be_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_le_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16(&units).map_err(|e| e.to_string())
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+
+/// Errors reported by the fallible diffing entry points and the plugin
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffError {
+    /// An input could not be decoded, e.g. invalid base64 or UTF-8
+    InvalidEncoding { field: String, message: String },
+    /// The input JSON was malformed or missing required fields
+    MalformedJson { message: String },
+    /// An input was larger than the configured limit, in bytes
+    SizeLimitExceeded {
+        field: String,
+        limit: usize,
+        actual: usize,
+    },
+    /// An input had more tokens than the configured limit
+    TokenLimitExceeded {
+        field: String,
+        limit: usize,
+        actual: usize,
+    },
+    /// The diff would need more memory than the configured limit, in bytes
+    MemoryLimitExceeded { limit: usize, estimate: usize },
+    /// The diff did not finish within its time or iteration budget
+    Timeout,
+}
+
+impl DiffError {
+    /// Stable machine readable code for the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiffError::InvalidEncoding { .. } => "invalid_encoding",
+            DiffError::MalformedJson { .. } => "malformed_json",
+            DiffError::SizeLimitExceeded { .. } => "size_limit_exceeded",
+            DiffError::TokenLimitExceeded { .. } => "token_limit_exceeded",
+            DiffError::MemoryLimitExceeded { .. } => "memory_limit_exceeded",
+            DiffError::Timeout => "timeout",
+        }
+    }
+
+    /// Input field the error relates to, if any
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            DiffError::InvalidEncoding { field, .. }
+            | DiffError::SizeLimitExceeded { field, .. }
+            | DiffError::TokenLimitExceeded { field, .. } => Some(field),
+            DiffError::MalformedJson { .. }
+            | DiffError::MemoryLimitExceeded { .. }
+            | DiffError::Timeout => None,
+        }
+    }
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffError::InvalidEncoding { field, message } => {
+                write!(f, "invalid encoding in `{}`: {}", field, message)
+            }
+            DiffError::MalformedJson { message } => write!(f, "malformed JSON: {}", message),
+            DiffError::SizeLimitExceeded {
+                field,
+                limit,
+                actual,
+            } => write!(
+                f,
+                "`{}` is {} bytes, more than the limit of {}",
+                field, actual, limit
+            ),
+            DiffError::TokenLimitExceeded {
+                field,
+                limit,
+                actual,
+            } => write!(
+                f,
+                "`{}` has {} tokens, more than the limit of {}",
+                field, actual, limit
+            ),
+            DiffError::MemoryLimitExceeded { limit, estimate } => write!(
+                f,
+                "the diff needs about {} bytes of memory, more than the limit of {}",
+                estimate, limit
+            ),
+            DiffError::Timeout => write!(f, "diff exceeded its time budget"),
+        }
+    }
+}
+
+impl std::error::Error for DiffError {}
+
+impl From<serde_json::Error> for DiffError {
+    fn from(err: serde_json::Error) -> Self {
+        DiffError::MalformedJson {
+            message: err.to_string(),
+        }
+    }
+}
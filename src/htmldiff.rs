@@ -1,5 +1,53 @@
+use crate::document::{self, Document, HeadChange};
+use crate::error::DiffError;
+use crate::merge::{self, MergeResult};
+use crate::options::{
+    self, Algorithm, DiffMode, ElementStrategy, EntityMode, Granularity, HtmlDiffOptions,
+    LimitFallback, Limits, WhitespaceMode,
+};
+use crate::patch::{self, Resolution};
+use crate::render::BLOCK_ELEMENTS;
+use crate::sanitize;
+use crate::stats::DiffStats;
+use crate::tag::{self, escape_attribute, Tag, TagSource};
+use crate::tokenizer::{
+    DefaultTokenPolicy, TokenKind, TokenPolicy, Tokenizer, FOREIGN_ELEMENTS, MEDIA_CONTAINERS,
+};
 use regex::Regex;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+/// Void media elements that are diffed as a single unit
+const MEDIA_ELEMENTS: &[&str] = &["img", "source", "track", "embed"];
+
+/// Inline elements that only change how text is presented
+const FORMAT_ELEMENTS: &[&str] = &[
+    "b", "strong", "i", "em", "u", "s", "strike", "mark", "sub", "sup", "small",
+];
+
+/// Elements that never have content or an end tag
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements whose content is never rendered as text
+const HIDDEN_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Anchor attributes that change where or how a link navigates
+const LINK_ATTRIBUTES: &[&str] = &["href", "target", "rel"];
+
+/// Share of words two changed regions must have in common before
+/// hierarchical alignment compares them word by word
+const SIMILARITY_THRESHOLD: f64 = 0.5;
 
 #[derive(Debug, Clone)]
 struct Match {
@@ -24,6 +72,15 @@ impl Match {
     }
 }
 
+/// Units a token list is split into by hierarchical alignment, from the
+/// coarsest to the finest
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Level {
+    Block,
+    Sentence,
+    Word,
+}
+
 #[derive(Debug)]
 struct SearchRange {
     start_in_before: usize,
@@ -48,153 +105,456 @@ struct DiffOperation {
     end_in_after: Option<usize>,
 }
 
+impl DiffOperation {
+    /// Tokens covered by this operation on the "before" side
+    fn before_slice<'a, T>(&self, tokens: &'a [T]) -> &'a [T] {
+        match self.end_in_before {
+            Some(end) => &tokens[self.start_in_before..=end],
+            None => &[],
+        }
+    }
+
+    /// Tokens covered by this operation on the "after" side
+    fn after_slice<'a, T>(&self, tokens: &'a [T]) -> &'a [T] {
+        match self.end_in_after {
+            Some(end) => &tokens[self.start_in_after..=end],
+            None => &[],
+        }
+    }
+}
+
+/// Identity of a rendered change: the position of its operation among the
+/// changes of a diff, and the kind of change
+#[derive(Debug, Clone, Copy)]
+struct ChangeId {
+    id: usize,
+    action: Operation,
+}
+
+impl ChangeId {
+    /// `data-diff-id` and `data-diff-op` attributes of the change
+    fn attributes(&self) -> [(&'static str, String); 2] {
+        let op = match self.action {
+            Operation::Insert => "insert",
+            Operation::Delete => "delete",
+            Operation::Equal | Operation::Replace => "replace",
+        };
+        [
+            ("data-diff-id", self.id.to_string()),
+            ("data-diff-op", op.to_string()),
+        ]
+    }
+}
+
+/// Tokens of both inputs together with the operations between them
+struct Plan {
+    before_tokens: Vec<String>,
+    after_tokens: Vec<String>,
+    operations: Vec<DiffOperation>,
+}
+
+/// A run of visible text matched in text mode, pointing at the tokens it
+/// came from. Block boundaries are empty runs keyed `"\n"`.
+struct TextRun {
+    key: String,
+    tokens: Range<usize>,
+}
+
+/// Tokens and text runs of both inputs together with the operations
+/// between the runs
+struct TextPlan {
+    before_tokens: Vec<String>,
+    after_tokens: Vec<String>,
+    before_runs: Vec<TextRun>,
+    after_runs: Vec<TextRun>,
+    operations: Vec<DiffOperation>,
+}
+
+/// How the body of a diff is matched
+enum BodyPlan {
+    Markup(Plan),
+    Text(TextPlan),
+}
+
+/// Tracks the iteration and time budget of a single diff
+struct Budget {
+    iterations: Cell<u64>,
+    max_iterations: Option<u64>,
+    #[cfg(not(target_arch = "wasm32"))]
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    fn new(limits: &Limits) -> Self {
+        Self {
+            iterations: Cell::new(0),
+            max_iterations: limits.max_iterations,
+            #[cfg(not(target_arch = "wasm32"))]
+            deadline: limits
+                .max_duration_ms
+                .map(|ms| Instant::now() + Duration::from_millis(ms)),
+        }
+    }
+
+    /// Records `n` token comparisons, failing once the budget is spent
+    fn spend(&self, n: u64) -> Result<(), DiffError> {
+        let iterations = self.iterations.get().saturating_add(n);
+        self.iterations.set(iterations);
+        if self.max_iterations.is_some_and(|max| iterations > max) {
+            return Err(DiffError::Timeout);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() > deadline)
+        {
+            return Err(DiffError::Timeout);
+        }
+        Ok(())
+    }
+
+    /// Starts counting iterations again, keeping the original deadline
+    fn reset_iterations(&self) {
+        self.iterations.set(0);
+    }
+}
+
+/// Kind of a single diff operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Equal,
+    Insert,
+    Delete,
+    Replace,
+}
+
+/// One operation of a diff, covering a range of tokens on each side
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffOp {
+    pub kind: ChangeKind,
+    /// Token indices covered in the "before" document
+    pub before: Range<usize>,
+    /// Token indices covered in the "after" document
+    pub after: Range<usize>,
+    /// Byte offsets covered in the "before" input
+    pub before_bytes: Range<usize>,
+    /// Byte offsets covered in the "after" input
+    pub after_bytes: Range<usize>,
+    /// Elements enclosing the operation in the "before" document, e.g.
+    /// `body>div.container>p#p1`
+    pub before_path: String,
+    /// Elements enclosing the operation in the "after" document
+    pub after_path: String,
+    pub before_text: String,
+    pub after_text: String,
+}
+
+/// How an operation takes part in a formatting-only change
+enum FormatSpan {
+    Open(FormatChange),
+    Close,
+}
+
+/// A single attribute that differs between a "before" and "after" element
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeChange {
+    pub element: String,
+    pub attribute: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Text whose formatting changed while its wording stayed the same,
+/// e.g. a word that was wrapped in `<strong>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatChange {
+    /// Formatting elements that were removed, e.g. `["em"]`
+    pub removed: Vec<String>,
+    /// Formatting elements that were added, e.g. `["strong"]`
+    pub added: Vec<String>,
+    /// The text whose formatting changed
+    pub text: String,
+}
+
+impl FormatChange {
+    /// Marker value such as `-em +strong` used for `data-diff-format`
+    fn marker(&self) -> String {
+        let removed = self.removed.iter().map(|name| format!("-{}", name));
+        let added = self.added.iter().map(|name| format!("+{}", name));
+        removed.chain(added).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Result of comparing two HTML documents
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffResult {
+    /// Rendered diff markup, identical to the output of [`HtmlDiff::diff`]
+    pub html: String,
+    /// Attribute changes found on replaced elements such as images and
+    /// links. Always empty in text mode, which ignores markup.
+    pub attribute_changes: Vec<AttributeChange>,
+    /// Formatting toggled on unchanged text. Always empty in text mode.
+    pub format_changes: Vec<FormatChange>,
+    /// Title, meta, link and script changes when diffing full documents
+    pub head_changes: Vec<HeadChange>,
+}
+
 /// HTML diffing utility that compares HTML content and generates
 /// a visual diff with <ins> and <del> tags
 #[derive(Debug)]
 pub struct HtmlDiff {
-    whitespace_regex: Regex,
     tag_regex: Regex,
-    char_regex: Regex,
+    options: HtmlDiffOptions,
+    policy: Arc<dyn TokenPolicy>,
 }
 
 impl HtmlDiff {
     pub fn new() -> Self {
+        Self::with_options(HtmlDiffOptions::default())
+    }
+
+    pub fn with_options(mut options: HtmlDiffOptions) -> Self {
+        options.elements = options
+            .elements
+            .into_iter()
+            .map(|(name, strategy)| (name.to_ascii_lowercase(), strategy))
+            .collect();
         Self {
-            whitespace_regex: Regex::new(r"\s").unwrap(),
             tag_regex: Regex::new(r"^\s*<[^>]+>\s*$").unwrap(),
-            char_regex: Regex::new(r"[\w\#@]").unwrap(),
+            options,
+            policy: Arc::new(DefaultTokenPolicy),
         }
     }
 
-    fn is_whitespace(&self, char: char) -> bool {
-        self.whitespace_regex.is_match(&char.to_string())
+    /// Replaces the [`TokenPolicy`] deciding which elements are kept whole
+    pub fn with_token_policy<P: TokenPolicy + 'static>(mut self, policy: P) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    pub fn options(&self) -> &HtmlDiffOptions {
+        &self.options
+    }
+
+    /// Classifies a token returned by [`HtmlDiff::html_to_tokens`]. Tokens
+    /// grouped by a granularity coarser than words are classified by how
+    /// they are diffed, which is as text unless they hold a single tag.
+    pub fn token_kind(&self, token: &str) -> TokenKind {
+        if self.is_unit(token) && !self.tag_regex.is_match(token) {
+            TokenKind::Element
+        } else if token.starts_with("<!--") && token.ends_with("-->") {
+            TokenKind::Comment
+        } else if self.is_tag(token) {
+            TokenKind::Tag
+        } else if token.chars().all(char::is_whitespace) {
+            TokenKind::Whitespace
+        } else if token.starts_with('&') && token.ends_with(';') {
+            TokenKind::Entity
+        } else {
+            TokenKind::Text
+        }
     }
 
     fn is_tag(&self, token: &str) -> bool {
-        self.tag_regex.is_match(token)
-            || token.starts_with("<!--")
-            || token.ends_with("-->")
-            || token.ends_with("/>")
+        if self.tag_regex.is_match(token) {
+            return true;
+        }
+        // Comments containing `>` and tags split at a `>` inside an
+        // attribute value, but not text grouped with the tags that follow it
+        let rest = token.get(1..).unwrap_or("");
+        (token.starts_with("<!--") && rest.find("-->").is_none_or(|end| end + 4 == token.len()))
+            || ((token.ends_with("-->") || token.ends_with("/>")) && !rest.contains('<'))
     }
 
-    fn is_start_of_tag(&self, char: char) -> bool {
-        char == '<'
+    /// Whether a token is matched and rendered as a single unit: a void
+    /// media element, or an atomic element grouped with its content
+    fn is_unit(&self, token: &str) -> bool {
+        if token.starts_with("</") {
+            return false;
+        }
+        tag::element_name(token)
+            .is_some_and(|name| MEDIA_ELEMENTS.contains(&name.as_str()) || self.is_atomic(&name))
     }
 
-    fn is_start_of_entity(&self, char: char) -> bool {
-        char == '&'
+    /// Whether attribute changes are reported for a replaced unit: media,
+    /// SVG and MathML, and elements diffed with `DiffAttributesOnly`. Other
+    /// atomic elements, such as those of a custom policy, are only shown as
+    /// replaced.
+    fn reports_attributes(&self, token: &str) -> bool {
+        self.is_unit(token)
+            && tag::element_name(token).is_some_and(|name| {
+                [MEDIA_ELEMENTS, MEDIA_CONTAINERS, FOREIGN_ELEMENTS]
+                    .iter()
+                    .any(|names| names.contains(&name.as_str()))
+                    || self.strategy(&name) == Some(ElementStrategy::DiffAttributesOnly)
+            })
     }
 
-    fn is_end_of_tag(&self, char: char) -> bool {
-        char == '>'
+    /// The strategy configured for an element, if any
+    fn strategy(&self, name: &str) -> Option<ElementStrategy> {
+        self.options.elements.get(name).copied()
     }
 
-    fn is_end_of_entity(&self, char: char) -> bool {
-        char == ';'
+    /// Whether an element is grouped into a single token with its content.
+    /// SVG and MathML can't hold diff markers, so they are atomic whatever
+    /// the policy unless a strategy is configured for them.
+    fn is_atomic(&self, name: &str) -> bool {
+        match self.strategy(name) {
+            Some(ElementStrategy::Atomic | ElementStrategy::DiffAttributesOnly) => true,
+            Some(ElementStrategy::DiffChildren) => false,
+            None => FOREIGN_ELEMENTS.contains(&name) || self.policy.is_atomic(name),
+        }
     }
 
+    /// Splits HTML into the tokens that are matched, grouped according to
+    /// the configured [`Granularity`]
     pub fn html_to_tokens(&self, html: &str) -> Vec<String> {
-        let mut mode = "char";
-        let mut current_word = String::new();
-        let mut words = Vec::new();
-
-        for char in html.chars() {
-            match mode {
-                "tag" => {
-                    if self.is_end_of_tag(char) {
-                        current_word.push('>');
-                        words.push(current_word);
-                        current_word = String::new();
-                        mode = if self.is_whitespace(char) {
-                            "whitespace"
-                        } else {
-                            "char"
-                        };
-                    } else {
-                        current_word.push(char);
+        self.group_granularity(self.word_tokens(html))
+    }
+
+    /// Splits HTML into word-level tokens regardless of the configured
+    /// granularity, for reading markup back rather than matching it
+    pub(crate) fn word_tokens(&self, html: &str) -> Vec<String> {
+        self.group_atomic(self.html_to_tokens_ungrouped(html))
+    }
+
+    fn html_to_tokens_ungrouped(&self, html: &str) -> Vec<String> {
+        Tokenizer::new(html)
+            .map(|token| token.text(html).to_string())
+            .collect()
+    }
+
+    /// Merges atomic elements such as `<video>…</video>` into a single token
+    /// so that they are matched and rendered as one unit
+    fn group_atomic(&self, tokens: Vec<String>) -> Vec<String> {
+        let mut grouped = Vec::with_capacity(tokens.len());
+        let mut open: Option<(String, usize, Vec<String>)> = None;
+
+        for token in tokens {
+            if let Some((name, depth, buffer)) = open.as_mut() {
+                if let Some(tag) = Tag::parse(&token).filter(|tag| tag.name == *name) {
+                    if tag.closing {
+                        *depth -= 1;
+                    } else if !token.ends_with("/>") {
+                        *depth += 1;
                     }
                 }
-                "entity" => {
-                    if self.is_end_of_entity(char) {
-                        current_word.push(';');
-                        words.push(current_word);
-                        current_word = String::new();
-                        mode = if self.is_whitespace(char) {
-                            "whitespace"
-                        } else {
-                            "char"
-                        };
+                buffer.push(token);
+                if *depth == 0 {
+                    grouped.push(buffer.concat());
+                    open = None;
+                }
+                continue;
+            }
+
+            match Tag::parse(&token) {
+                Some(tag)
+                    if !tag.closing && !token.ends_with("/>") && self.is_atomic(&tag.name) =>
+                {
+                    open = Some((tag.name, 1, vec![token]));
+                }
+                _ => grouped.push(token),
+            }
+        }
+
+        // An unclosed container is left as individual tokens
+        if let Some((_, _, buffer)) = open {
+            grouped.extend(buffer);
+        }
+
+        grouped
+    }
+
+    /// Regroups word-level tokens into characters, sentences, lines or
+    /// blocks
+    fn group_granularity(&self, tokens: Vec<String>) -> Vec<String> {
+        let is_markup = |token: &str| self.is_tag(token) || self.is_unit(token);
+        match self.options.granularity {
+            Granularity::Word => tokens,
+            Granularity::Character => tokens
+                .into_iter()
+                .flat_map(|token| {
+                    if is_markup(&token) || token.starts_with('&') {
+                        vec![token]
                     } else {
-                        current_word.push(char);
+                        token.chars().map(String::from).collect()
                     }
-                }
-                "char" => {
-                    if self.is_start_of_tag(char) {
-                        if !current_word.is_empty() {
-                            words.push(current_word);
-                            current_word = String::new();
-                        }
-                        current_word.push('<');
-                        mode = "tag";
-                    } else if self.is_start_of_entity(char) {
-                        if !current_word.is_empty() {
-                            words.push(current_word);
-                            current_word = String::new();
+                })
+                .collect(),
+            Granularity::Sentence => {
+                let mut grouped = Vec::new();
+                let mut sentence = String::new();
+                let mut terminated = false;
+                for token in tokens {
+                    if is_markup(&token) {
+                        if !sentence.is_empty() {
+                            grouped.push(std::mem::take(&mut sentence));
                         }
-                        current_word.push('&');
-                        mode = "entity";
-                    } else if self.is_whitespace(char) {
-                        if !current_word.is_empty() {
-                            words.push(current_word);
-                            current_word = String::new();
+                        grouped.push(token);
+                        terminated = false;
+                        continue;
+                    }
+                    let whitespace = token.chars().all(|c| c.is_whitespace());
+                    sentence.push_str(&token);
+                    if whitespace && terminated {
+                        grouped.push(std::mem::take(&mut sentence));
+                    }
+                    if !whitespace {
+                        terminated = token.ends_with(['.', '!', '?']);
+                    }
+                }
+                if !sentence.is_empty() {
+                    grouped.push(sentence);
+                }
+                grouped
+            }
+            // Tags stay separate so that markers never wrap half an element
+            Granularity::Line => {
+                let mut grouped = Vec::new();
+                let mut line = String::new();
+                for token in tokens {
+                    if is_markup(&token) {
+                        if !line.is_empty() {
+                            grouped.push(std::mem::take(&mut line));
                         }
-                        current_word.push(char);
-                        mode = "whitespace";
-                    } else if self.char_regex.is_match(&char.to_string()) {
-                        current_word.push(char);
-                    } else {
-                        if !current_word.is_empty() {
-                            words.push(current_word);
-                            current_word = String::new();
+                        grouped.push(token);
+                        continue;
+                    }
+                    for part in token.split_inclusive('\n') {
+                        line.push_str(part);
+                        if part.ends_with('\n') {
+                            grouped.push(std::mem::take(&mut line));
                         }
-                        current_word.push(char);
                     }
                 }
-                "whitespace" => {
-                    if self.is_start_of_tag(char) {
-                        if !current_word.is_empty() {
-                            words.push(current_word);
-                            current_word = String::new();
-                        }
-                        current_word.push('<');
-                        mode = "tag";
-                    } else if self.is_start_of_entity(char) {
-                        if !current_word.is_empty() {
-                            words.push(current_word);
-                            current_word = String::new();
+                if !line.is_empty() {
+                    grouped.push(line);
+                }
+                grouped
+            }
+            Granularity::Block => {
+                let mut grouped = Vec::new();
+                let mut run = String::new();
+                for token in tokens {
+                    let is_block = self.is_tag(&token)
+                        && tag::element_name(&token)
+                            .is_some_and(|name| BLOCK_ELEMENTS.contains(&name.as_str()));
+                    if is_block {
+                        if !run.is_empty() {
+                            grouped.push(std::mem::take(&mut run));
                         }
-                        current_word.push('&');
-                        mode = "entity";
-                    } else if self.is_whitespace(char) {
-                        current_word.push(char);
+                        grouped.push(token);
                     } else {
-                        if !current_word.is_empty() {
-                            words.push(current_word);
-                            current_word = String::new();
-                        }
-                        current_word.push(char);
-                        mode = "char";
+                        run.push_str(&token);
                     }
                 }
-                _ => {}
+                if !run.is_empty() {
+                    grouped.push(run);
+                }
+                grouped
             }
         }
-
-        if !current_word.is_empty() {
-            words.push(current_word);
-        }
-
-        words
     }
 
     fn create_index(
@@ -202,13 +562,11 @@ impl HtmlDiff {
         find_these: &[String],
         in_these: &[String],
     ) -> HashMap<String, Vec<usize>> {
-        let mut index = HashMap::new();
-        for token in find_these {
-            index.insert(token.clone(), Vec::new());
-            for (i, t) in in_these.iter().enumerate() {
-                if t == token {
-                    index.get_mut(token).unwrap().push(i);
-                }
+        let wanted: HashSet<&String> = find_these.iter().collect();
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, t) in in_these.iter().enumerate() {
+            if wanted.contains(t) {
+                index.entry(t.clone()).or_default().push(i);
             }
         }
         index
@@ -217,30 +575,33 @@ impl HtmlDiff {
     fn find_match(
         &self,
         before_tokens: &[String],
-        after_tokens: &[String],
         index: &HashMap<String, Vec<usize>>,
-        start_in_before: usize,
-        end_in_before: usize,
-        start_in_after: usize,
-        end_in_after: usize,
-    ) -> Option<Match> {
-        let _ = after_tokens;
-        let mut best_match_in_before = start_in_before;
-        let mut best_match_in_after = start_in_after;
+        range: &SearchRange,
+        budget: &Budget,
+    ) -> Result<Option<Match>, DiffError> {
+        let mut best_match_in_before = range.start_in_before;
+        let mut best_match_in_after = range.start_in_after;
         let mut best_match_length = 0;
 
-        let mut match_length_at = HashMap::new();
+        // Both maps are reused across iterations to avoid reallocating
+        let mut match_length_at: HashMap<usize, usize> = HashMap::new();
+        let mut new_match_length_at: HashMap<usize, usize> = HashMap::new();
 
-        for index_in_before in start_in_before..end_in_before {
-            let mut new_match_length_at = HashMap::new();
-            let looking_for = &before_tokens[index_in_before];
+        for (index_in_before, looking_for) in before_tokens
+            .iter()
+            .enumerate()
+            .take(range.end_in_before)
+            .skip(range.start_in_before)
+        {
+            new_match_length_at.clear();
 
             if let Some(locations_in_after) = index.get(looking_for) {
+                budget.spend(locations_in_after.len() as u64)?;
                 for &index_in_after in locations_in_after {
-                    if index_in_after < start_in_after {
+                    if index_in_after < range.start_in_after {
                         continue;
                     }
-                    if index_in_after >= end_in_after {
+                    if index_in_after >= range.end_in_after {
                         break;
                     }
 
@@ -262,17 +623,17 @@ impl HtmlDiff {
                 }
             }
 
-            match_length_at = new_match_length_at;
+            std::mem::swap(&mut match_length_at, &mut new_match_length_at);
         }
 
         if best_match_length != 0 {
-            Some(Match::new(
+            Ok(Some(Match::new(
                 best_match_in_before,
                 best_match_in_after,
                 best_match_length,
-            ))
+            )))
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -280,7 +641,8 @@ impl HtmlDiff {
         &self,
         before_tokens: &[String],
         after_tokens: &[String],
-    ) -> Vec<Match> {
+        budget: &Budget,
+    ) -> Result<Vec<Match>, DiffError> {
         let mut matching_blocks = Vec::new();
         let index = self.create_index(before_tokens, after_tokens);
 
@@ -292,15 +654,7 @@ impl HtmlDiff {
         }];
 
         while let Some(current) = stack.pop() {
-            if let Some(match_) = self.find_match(
-                before_tokens,
-                after_tokens,
-                &index,
-                current.start_in_before,
-                current.end_in_before,
-                current.start_in_after,
-                current.end_in_after,
-            ) {
+            if let Some(match_) = self.find_match(before_tokens, &index, &current, budget)? {
                 // Push right range first (LIFO)
                 if match_.end_in_before + 1 < current.end_in_before
                     && match_.end_in_after + 1 < current.end_in_after
@@ -329,20 +683,332 @@ impl HtmlDiff {
             }
         }
 
-        matching_blocks.sort_by(|a, b| a.start_in_before.cmp(&b.start_in_before));
-        matching_blocks
+        matching_blocks.sort_by_key(|m| m.start_in_before);
+        Ok(matching_blocks)
+    }
+
+    /// Matching blocks found by the longest common subsequence of the tokens
+    fn find_lcs_blocks(
+        &self,
+        before_tokens: &[String],
+        after_tokens: &[String],
+        budget: &Budget,
+    ) -> Result<Vec<Match>, DiffError> {
+        // The LCS table compares every pair of tokens up front
+        budget.spend((before_tokens.len() as u64).saturating_mul(after_tokens.len() as u64))?;
+
+        let mut matching_blocks = Vec::new();
+        let mut position_in_before = 0;
+        let mut position_in_after = 0;
+        let mut run: Option<(usize, usize, usize)> = None;
+
+        for result in diff::slice(before_tokens, after_tokens) {
+            match result {
+                diff::Result::Both(_, _) => {
+                    match run.as_mut() {
+                        Some((_, _, length)) => *length += 1,
+                        None => run = Some((position_in_before, position_in_after, 1)),
+                    }
+                    position_in_before += 1;
+                    position_in_after += 1;
+                    continue;
+                }
+                diff::Result::Left(_) => position_in_before += 1,
+                diff::Result::Right(_) => position_in_after += 1,
+            }
+            if let Some((start_in_before, start_in_after, length)) = run.take() {
+                matching_blocks.push(Match::new(start_in_before, start_in_after, length));
+            }
+        }
+        if let Some((start_in_before, start_in_after, length)) = run {
+            matching_blocks.push(Match::new(start_in_before, start_in_after, length));
+        }
+
+        Ok(matching_blocks)
+    }
+
+    /// Matching blocks found by aligning block elements, then sentences
+    /// within the changed blocks, then words within similar sentences
+    fn find_hierarchical_blocks(
+        &self,
+        before_tokens: &[String],
+        after_tokens: &[String],
+        budget: &Budget,
+    ) -> Result<Vec<Match>, DiffError> {
+        let mut matches = Vec::new();
+        self.align(
+            before_tokens,
+            after_tokens,
+            (0..before_tokens.len(), 0..after_tokens.len()),
+            Level::Block,
+            budget,
+            &mut matches,
+        )?;
+
+        // Runs matched at different levels may touch
+        let mut merged: Vec<Match> = Vec::with_capacity(matches.len());
+        for match_ in matches {
+            match merged.last_mut() {
+                Some(last)
+                    if last.end_in_before + 1 == match_.start_in_before
+                        && last.end_in_after + 1 == match_.start_in_after =>
+                {
+                    *last = Match::new(
+                        last.start_in_before,
+                        last.start_in_after,
+                        last.length + match_.length,
+                    );
+                }
+                _ => merged.push(match_),
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Aligns two regions unit by unit at `level`, then aligns the regions
+    /// between equal units at the next level down
+    fn align(
+        &self,
+        before_tokens: &[String],
+        after_tokens: &[String],
+        (before, after): (Range<usize>, Range<usize>),
+        level: Level,
+        budget: &Budget,
+        matches: &mut Vec<Match>,
+    ) -> Result<(), DiffError> {
+        if before.is_empty() || after.is_empty() {
+            return Ok(());
+        }
+        if level == Level::Word {
+            if self.similarity(&before_tokens[before.clone()], &after_tokens[after.clone()])
+                < SIMILARITY_THRESHOLD
+            {
+                return Ok(());
+            }
+            let found = self.find_matching_blocks(
+                &before_tokens[before.clone()],
+                &after_tokens[after.clone()],
+                budget,
+            )?;
+            matches.extend(found.into_iter().map(|m| {
+                Match::new(
+                    m.start_in_before + before.start,
+                    m.start_in_after + after.start,
+                    m.length,
+                )
+            }));
+            return Ok(());
+        }
+
+        let before_units = self.units(before_tokens, before.clone(), level);
+        let after_units = self.units(after_tokens, after.clone(), level);
+        // Joined with a separator so that equal keys span equal token counts
+        let keys = |tokens: &[String], units: &[Range<usize>]| -> Vec<String> {
+            units
+                .iter()
+                .map(|unit| tokens[unit.clone()].join("\0"))
+                .collect()
+        };
+        let mut unit_matches = self.find_matching_blocks(
+            &keys(before_tokens, &before_units),
+            &keys(after_tokens, &after_units),
+            budget,
+        )?;
+        unit_matches.push(Match::new(before_units.len(), after_units.len(), 0));
+
+        let next = match level {
+            Level::Block => Level::Sentence,
+            _ => Level::Word,
+        };
+        let (mut in_before, mut in_after) = (before.start, after.start);
+        for unit_match in unit_matches {
+            let start_in_before = before_units
+                .get(unit_match.start_in_before)
+                .map_or(before.end, |unit| unit.start);
+            let start_in_after = after_units
+                .get(unit_match.start_in_after)
+                .map_or(after.end, |unit| unit.start);
+            self.align(
+                before_tokens,
+                after_tokens,
+                (in_before..start_in_before, in_after..start_in_after),
+                next,
+                budget,
+                matches,
+            )?;
+            if unit_match.length == 0 {
+                break;
+            }
+
+            let end_in_before = before_units[unit_match.end_in_before].end;
+            let end_in_after = after_units[unit_match.end_in_after].end;
+            matches.push(Match::new(
+                start_in_before,
+                start_in_after,
+                end_in_before - start_in_before,
+            ));
+            in_before = end_in_before;
+            in_after = end_in_after;
+        }
+        Ok(())
+    }
+
+    /// Splits a region into units: every block-level tag on its own and
+    /// either the runs between them or the sentences of those runs
+    fn units(&self, tokens: &[String], range: Range<usize>, level: Level) -> Vec<Range<usize>> {
+        let mut units = Vec::new();
+        let mut start = range.start;
+        let mut terminated = false;
+        for i in range.clone() {
+            let token = &tokens[i];
+            let is_block = self.is_tag(token)
+                && tag::element_name(token)
+                    .is_some_and(|name| BLOCK_ELEMENTS.contains(&name.as_str()));
+            if is_block {
+                if start < i {
+                    units.push(start..i);
+                }
+                units.push(i..i + 1);
+                start = i + 1;
+                terminated = false;
+                continue;
+            }
+            if level != Level::Sentence || self.is_tag(token) || self.is_unit(token) {
+                continue;
+            }
+            let whitespace = token.chars().all(|c| c.is_whitespace());
+            if whitespace && terminated {
+                units.push(start..i + 1);
+                start = i + 1;
+            }
+            if !whitespace {
+                terminated = token.ends_with(['.', '!', '?']);
+            }
+        }
+        if start < range.end {
+            units.push(start..range.end);
+        }
+        units
+    }
+
+    /// Dice coefficient of the words of two regions, ignoring whitespace
+    /// and markup
+    fn similarity(&self, before: &[String], after: &[String]) -> f64 {
+        let words = |tokens: &[String]| -> HashMap<String, usize> {
+            let mut counts = HashMap::new();
+            for token in tokens {
+                if !self.is_tag(token) && !token.chars().all(|c| c.is_whitespace()) {
+                    *counts.entry(token.to_lowercase()).or_insert(0) += 1;
+                }
+            }
+            counts
+        };
+        let before_words = words(before);
+        let after_words = words(after);
+        let total: usize = before_words.values().chain(after_words.values()).sum();
+        if total == 0 {
+            // Only markup and whitespace changed
+            return 1.0;
+        }
+        let common: usize = before_words
+            .iter()
+            .map(|(word, count)| (*count).min(after_words.get(word).copied().unwrap_or(0)))
+            .sum();
+        2.0 * common as f64 / total as f64
+    }
+
+    /// Key a token is matched by, normalized according to the whitespace
+    /// and entity modes
+    pub(crate) fn comparison_key(&self, token: &str) -> String {
+        if !self.options.elements.is_empty() && token.starts_with('<') {
+            if let Some(key) = self.element_key(token) {
+                return key;
+            }
+        }
+        if self.options.whitespace == WhitespaceMode::Collapse
+            && token.chars().all(|c| c.is_whitespace())
+        {
+            return " ".to_string();
+        }
+        if self.options.entities == EntityMode::Decode && token.starts_with('&') {
+            if let Some(decoded) = options::decode_entity(token) {
+                return decoded.to_string();
+            }
+        }
+        token.to_string()
+    }
+
+    /// Key of a tag or grouped element whose strategy compares only part of
+    /// it: the bare tag for `DiffChildren` and the start tag for
+    /// `DiffAttributesOnly`
+    fn element_key(&self, token: &str) -> Option<String> {
+        let name = tag::element_name(token)?;
+        match self.strategy(&name)? {
+            ElementStrategy::DiffChildren if token.starts_with("</") => {
+                Some(format!("</{}>", name))
+            }
+            ElementStrategy::DiffChildren => Some(format!("<{}>", name)),
+            ElementStrategy::DiffAttributesOnly if !token.starts_with("</") => {
+                token.find('>').map(|end| token[..=end].to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Matching blocks of the two token lists, in order, found with the
+    /// configured algorithm
+    fn find_matches(
+        &self,
+        before_tokens: &[String],
+        after_tokens: &[String],
+        budget: &Budget,
+    ) -> Result<Vec<Match>, DiffError> {
+        let before_keys: Vec<String> = before_tokens
+            .iter()
+            .map(|t| self.comparison_key(t))
+            .collect();
+        let after_keys: Vec<String> = after_tokens
+            .iter()
+            .map(|t| self.comparison_key(t))
+            .collect();
+        match self.options.algorithm {
+            Algorithm::LongestMatch => self.find_matching_blocks(&before_keys, &after_keys, budget),
+            Algorithm::Lcs => self.find_lcs_blocks(&before_keys, &after_keys, budget),
+            Algorithm::Hierarchical => {
+                self.find_hierarchical_blocks(&before_keys, &after_keys, budget)
+            }
+        }
+    }
+
+    /// Runs of tokens shared by both lists as `(start_in_before,
+    /// start_in_after, length)`, within the configured token and iteration
+    /// limits
+    pub(crate) fn matching_runs(
+        &self,
+        before_tokens: &[String],
+        after_tokens: &[String],
+    ) -> Result<Vec<(usize, usize, usize)>, DiffError> {
+        self.check_token_limits(before_tokens, after_tokens)?;
+        let budget = Budget::new(&self.options.limits);
+        Ok(self
+            .find_matches(before_tokens, after_tokens, &budget)?
+            .into_iter()
+            .filter(|m| m.length > 0)
+            .map(|m| (m.start_in_before, m.start_in_after, m.length))
+            .collect())
     }
 
     fn calculate_operations(
         &self,
         before_tokens: &[String],
         after_tokens: &[String],
-    ) -> Vec<DiffOperation> {
+        budget: &Budget,
+    ) -> Result<Vec<DiffOperation>, DiffError> {
         let mut operations = Vec::new();
         let mut position_in_before = 0;
         let mut position_in_after = 0;
 
-        let mut matches = self.find_matching_blocks(before_tokens, after_tokens);
+        let mut matches = self.find_matches(before_tokens, after_tokens, budget)?;
         matches.push(Match::new(before_tokens.len(), after_tokens.len(), 0));
 
         for match_ in matches {
@@ -403,7 +1069,7 @@ impl HtmlDiff {
             position_in_after = position_in_after.min(after_tokens.len());
         }
 
-        operations
+        Ok(operations)
     }
 
     fn consecutive_where<F>(&self, start: usize, content: &[String], predicate: F) -> Vec<String>
@@ -426,72 +1092,1223 @@ impl HtmlDiff {
         }
     }
 
-    fn wrap(&self, tag: &str, content: &[String]) -> String {
-        let mut rendering = String::new();
+    /// Start tag of the insertion or deletion marker, with the configured
+    /// class, any extra classes and, if enabled, the change id
+    fn marker_start(&self, insert: bool, extra_class: Option<&str>, change: ChangeId) -> String {
+        let (tag, class) = if insert {
+            (&self.options.insert_tag, &self.options.insert_class)
+        } else {
+            (&self.options.delete_tag, &self.options.delete_class)
+        };
+        let mut marker = format!("<{}", tag);
+        let classes: Vec<&str> = class.as_deref().into_iter().chain(extra_class).collect();
+        if !classes.is_empty() {
+            marker.push_str(&format!(
+                " class=\"{}\"",
+                escape_attribute(&classes.join(" "))
+            ));
+        }
+        if self.options.change_ids {
+            for (name, value) in change.attributes() {
+                marker.push_str(&format!(" {}=\"{}\"", name, value));
+            }
+        }
+        marker.push('>');
+        marker
+    }
+
+    fn marker_end(&self, insert: bool) -> String {
+        if insert {
+            format!("</{}>", self.options.insert_tag)
+        } else {
+            format!("</{}>", self.options.delete_tag)
+        }
+    }
+
+    fn wrap<W: fmt::Write>(
+        &self,
+        insert: bool,
+        content: &[String],
+        change: ChangeId,
+        out: &mut W,
+    ) -> fmt::Result {
         let mut position = 0;
         let length = content.len();
+        let media_class = if insert { "diff-ins" } else { "diff-del" };
 
         while position < length {
-            let non_tags = self.consecutive_where(position, content, |token| !self.is_tag(token));
+            if self.is_unit(&content[position]) {
+                out.write_str(&self.marker_start(insert, Some(media_class), change))?;
+                out.write_str(&content[position])?;
+                out.write_str(&self.marker_end(insert))?;
+                position += 1;
+                continue;
+            }
+
+            let non_tags = self.consecutive_where(position, content, |token| {
+                !self.is_tag(token) && !self.is_unit(token)
+            });
             position += non_tags.len();
             if !non_tags.is_empty() {
-                rendering.push_str(&format!("<{}>{}</{}>", tag, non_tags.join(""), tag));
+                out.write_str(&self.marker_start(insert, None, change))?;
+                out.write_str(&non_tags.concat())?;
+                out.write_str(&self.marker_end(insert))?;
             }
 
             if position >= length {
                 break;
             }
 
-            let tags = self.consecutive_where(position, content, |token| self.is_tag(token));
+            let tags = self.consecutive_where(position, content, |token| {
+                self.is_tag(token) && !self.is_unit(token)
+            });
             position += tags.len();
-            rendering.push_str(&tags.join(""));
+            if self.options.mark_tag_changes {
+                let (open, close) =
+                    patch::tag_markers(insert, self.options.change_ids.then_some(change.id));
+                write!(out, "{}{}{}", open, tags.concat(), close)?;
+            } else {
+                out.write_str(&tags.concat())?;
+            }
         }
 
-        rendering
+        Ok(())
+    }
+
+    /// Collects attribute differences between replaced units that report
+    /// them, such as media elements, pairing them up in document order
+    fn media_attribute_changes(
+        &self,
+        before: &[String],
+        after: &[String],
+        changes: &mut Vec<AttributeChange>,
+    ) {
+        let before_media = before.iter().filter(|token| self.reports_attributes(token));
+        let after_media = after.iter().filter(|token| self.reports_attributes(token));
+
+        for (before_token, after_token) in before_media.zip(after_media) {
+            let before_tags = self.start_tags(before_token);
+            let after_tags = self.start_tags(after_token);
+            if before_tags.first().map(|t| &t.name) != after_tags.first().map(|t| &t.name) {
+                continue;
+            }
+
+            for (b, a) in before_tags.iter().zip(after_tags.iter()) {
+                if b.name == a.name {
+                    diff_attributes(b, a, changes);
+                }
+            }
+        }
+    }
+
+    /// Pairs up anchors replaced within a single operation. The old anchor is
+    /// dropped and the new one is kept, marked with `diff-link`, the old
+    /// values of any changed link attributes and a `data-diff-added` list of
+    /// the ones the old anchor didn't have. Returns the rewritten token
+    /// slices, or `None` when no paired anchor changed a link attribute.
+    /// The end tags of dropped anchors go with them when they fall inside
+    /// the operation; otherwise the shared end tag closes the new anchor.
+    fn link_changes(
+        &self,
+        before: &[String],
+        after: &[String],
+        change: ChangeId,
+        changes: &mut Vec<AttributeChange>,
+    ) -> Option<(Vec<String>, Vec<String>)> {
+        let anchors = |tokens: &[String]| -> Vec<(usize, Tag)> {
+            tokens
+                .iter()
+                .enumerate()
+                .filter_map(|(i, token)| Tag::parse(token).map(|tag| (i, tag)))
+                .filter(|(_, tag)| tag.name == "a" && !tag.closing)
+                .collect()
+        };
+        let before_anchors = anchors(before);
+        let after_anchors = anchors(after);
+        if before_anchors.is_empty() || before_anchors.len() != after_anchors.len() {
+            return None;
+        }
+
+        let mut after_marked = after.to_vec();
+        let mut paired = Vec::new();
+        let mut any_link_changed = false;
+        for ((before_index, old), (after_index, new)) in
+            before_anchors.into_iter().zip(after_anchors)
+        {
+            paired.push(before_index);
+            paired.extend(matching_close(before, before_index, "a"));
+            let mut marked = TagSource::new(&after[after_index]);
+            let mut link_changed = false;
+            let mut added = Vec::new();
+            for attribute in LINK_ATTRIBUTES {
+                let (old_value, new_value) = (old.attribute(attribute), new.attribute(attribute));
+                if old_value == new_value {
+                    continue;
+                }
+                link_changed = true;
+                match old_value {
+                    Some(old_value) => {
+                        marked.set_attribute(&format!("data-diff-old-{}", attribute), old_value)
+                    }
+                    None => added.push(*attribute),
+                }
+                changes.push(AttributeChange {
+                    element: "a".to_string(),
+                    attribute: attribute.to_string(),
+                    before: old_value.map(str::to_string),
+                    after: new_value.map(str::to_string),
+                });
+            }
+            if link_changed {
+                any_link_changed = true;
+                if !added.is_empty() {
+                    marked.set_attribute("data-diff-added", &added.join(" "));
+                }
+                marked.add_class("diff-link");
+                if self.options.change_ids {
+                    for (name, value) in change.attributes() {
+                        marked.set_attribute(name, &value);
+                    }
+                }
+                after_marked[after_index] = marked.into_string();
+            }
+        }
+        if !any_link_changed {
+            return None;
+        }
+
+        let before_rest = before
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !paired.contains(i))
+            .map(|(_, token)| token.clone())
+            .collect();
+
+        Some((before_rest, after_marked))
+    }
+
+    /// Returns the formatting tags of a token run when every token is a
+    /// start tag (or every token an end tag) of a formatting element
+    fn format_tags(&self, tokens: &[String], closing: bool) -> Option<Vec<String>> {
+        tokens
+            .iter()
+            .map(|token| {
+                Tag::parse(token)
+                    .filter(|tag| tag.closing == closing)
+                    .filter(|tag| FORMAT_ELEMENTS.contains(&tag.name.as_str()))
+                    .map(|tag| tag.name)
+            })
+            .collect()
+    }
+
+    /// Finds operations that only open or close formatting elements around
+    /// otherwise unchanged text, pairing each opening operation with the
+    /// operation that closes the same elements
+    fn pair_format_changes(
+        &self,
+        operations: &[DiffOperation],
+        before_tokens: &[String],
+        after_tokens: &[String],
+    ) -> HashMap<usize, FormatSpan> {
+        let mut spans = HashMap::new();
+        let mut open: Vec<(usize, Vec<String>, Vec<String>)> = Vec::new();
+
+        for (i, op) in operations.iter().enumerate() {
+            if op.action == Operation::Equal {
+                continue;
+            }
+            let before_val = op.before_slice(before_tokens);
+            let after_val = op.after_slice(after_tokens);
+
+            if let (Some(removed), Some(added)) = (
+                self.format_tags(before_val, false),
+                self.format_tags(after_val, false),
+            ) {
+                open.push((i, removed, added));
+                continue;
+            }
+
+            let (Some(mut closed_before), Some(mut closed_after)) = (
+                self.format_tags(before_val, true),
+                self.format_tags(after_val, true),
+            ) else {
+                open.clear();
+                continue;
+            };
+            closed_before.reverse();
+            closed_after.reverse();
+
+            match open.pop() {
+                Some((opened_at, removed, added))
+                    if removed == closed_before && added == closed_after =>
+                {
+                    let text = operations[opened_at + 1..i]
+                        .iter()
+                        .flat_map(|op| op.after_slice(after_tokens))
+                        .filter(|token| !self.is_tag(token))
+                        .map(String::as_str)
+                        .collect();
+                    spans.insert(
+                        opened_at,
+                        FormatSpan::Open(FormatChange {
+                            removed,
+                            added,
+                            text,
+                        }),
+                    );
+                    spans.insert(i, FormatSpan::Close);
+                }
+                _ => open.clear(),
+            }
+        }
+
+        spans
+    }
+
+    fn start_tags(&self, token: &str) -> Vec<Tag> {
+        self.html_to_tokens_ungrouped(token)
+            .iter()
+            .filter_map(|t| Tag::parse(t))
+            .filter(|t| !t.closing)
+            .collect()
     }
 
     pub fn diff(&self, before: &str, after: &str) -> String {
-        // Normalize input by handling different string delimiters
-        let before = before
-            .trim_matches('`')
-            .trim_matches('"')
-            .trim_matches('\'');
-        let after = after.trim_matches('`').trim_matches('"').trim_matches('\'');
+        self.compare(before, after).html
+    }
 
-        if before == after {
-            return before.to_string();
+    /// Like [`HtmlDiff::diff`], but fails instead of falling back when the
+    /// configured [`Limits`] are exceeded
+    pub fn try_diff(&self, before: &str, after: &str) -> Result<String, DiffError> {
+        Ok(self.try_compare(before, after)?.html)
+    }
+
+    /// Compares two HTML documents, returning the rendered diff along with
+    /// the structured changes found while rendering it.
+    ///
+    /// Full documents (anything with a `<body>`) are diffed inside `<body>`
+    /// only. The "after" `<head>` is kept as is and head changes are listed
+    /// in a summary section at the top of the body.
+    ///
+    /// If the configured [`Limits`] are exceeded even after any fallback,
+    /// the whole "before" body is rendered as deleted and the whole "after"
+    /// body as inserted. Inputs larger than `max_input_bytes` give an empty
+    /// result instead. Use [`HtmlDiff::try_compare`] to get the error.
+    pub fn compare(&self, before: &str, after: &str) -> DiffResult {
+        self.try_compare(before, after).unwrap_or_else(|_| {
+            let mut html = String::new();
+            // Writing to a String cannot fail
+            let _ = self.write_replacement(before, after, &mut html);
+            DiffResult {
+                html,
+                ..Default::default()
+            }
+        })
+    }
+
+    /// Like [`HtmlDiff::compare`], but fails instead of falling back when
+    /// the configured [`Limits`] are exceeded
+    pub fn try_compare(&self, before: &str, after: &str) -> Result<DiffResult, DiffError> {
+        let mut html = String::new();
+        match self.write_compare(before, after, &mut html) {
+            Ok(result) => Ok(DiffResult { html, ..result }),
+            Err(RenderError::Limit(err)) => Err(err),
+            Err(RenderError::Write(_)) => unreachable!("writing to a String cannot fail"),
+        }
+    }
+
+    /// Lists the operations that turn `before` into `after`. Full documents
+    /// are compared by their `<body>` content, as in [`HtmlDiff::compare`].
+    /// With the `sanitize` option, both inputs are sanitized first and the
+    /// byte offsets point into the sanitized inputs.
+    ///
+    /// If the configured [`Limits`] are exceeded even after any fallback, a
+    /// single replacement of all tokens is returned. Inputs larger than
+    /// `max_input_bytes` give no operations.
+    pub fn operations(&self, before: &str, after: &str) -> Vec<DiffOp> {
+        self.try_operations(before, after).unwrap_or_else(|_| {
+            if self
+                .check_input_size(normalize(before), normalize(after))
+                .is_err()
+            {
+                return Vec::new();
+            }
+            let (before, after) = (self.sanitized(before), self.sanitized(after));
+            let before = DiffedPart::of(&before);
+            let after = DiffedPart::of(&after);
+            vec![DiffOp {
+                kind: ChangeKind::Replace,
+                before: 0..self.html_to_tokens(before.html).len(),
+                after: 0..self.html_to_tokens(after.html).len(),
+                before_bytes: before.offset..before.offset + before.html.len(),
+                after_bytes: after.offset..after.offset + after.html.len(),
+                before_path: before.root.unwrap_or_default(),
+                after_path: after.root.unwrap_or_default(),
+                before_text: before.html.to_string(),
+                after_text: after.html.to_string(),
+            }]
+        })
+    }
+
+    /// Like [`HtmlDiff::operations`], but fails instead of falling back when
+    /// the configured [`Limits`] are exceeded
+    pub fn try_operations(&self, before: &str, after: &str) -> Result<Vec<DiffOp>, DiffError> {
+        self.check_input_size(normalize(before), normalize(after))?;
+        let (before, after) = (self.sanitized(before), self.sanitized(after));
+        let before = DiffedPart::of(&before);
+        let after = DiffedPart::of(&after);
+        let Plan {
+            before_tokens,
+            after_tokens,
+            operations,
+        } = self.plan(before.html, after.html)?;
+
+        let before_offsets = token_offsets(&before_tokens, before.offset);
+        let after_offsets = token_offsets(&after_tokens, after.offset);
+        let before_paths = self.element_paths(
+            before.root,
+            &before_tokens,
+            operations.iter().map(|op| op.start_in_before),
+        );
+        let after_paths = self.element_paths(
+            after.root,
+            &after_tokens,
+            operations.iter().map(|op| op.start_in_after),
+        );
+
+        Ok(operations
+            .iter()
+            .zip(before_paths.into_iter().zip(after_paths))
+            .map(|(op, (before_path, after_path))| {
+                let before_range = match op.end_in_before {
+                    Some(end) => op.start_in_before..end + 1,
+                    None => op.start_in_before..op.start_in_before,
+                };
+                let after_range = match op.end_in_after {
+                    Some(end) => op.start_in_after..end + 1,
+                    None => op.start_in_after..op.start_in_after,
+                };
+                DiffOp {
+                    kind: match op.action {
+                        Operation::Equal => ChangeKind::Equal,
+                        Operation::Insert => ChangeKind::Insert,
+                        Operation::Delete => ChangeKind::Delete,
+                        Operation::Replace => ChangeKind::Replace,
+                    },
+                    before_bytes: before_offsets[before_range.start]
+                        ..before_offsets[before_range.end],
+                    after_bytes: after_offsets[after_range.start]..after_offsets[after_range.end],
+                    before: before_range,
+                    after: after_range,
+                    before_path,
+                    after_path,
+                    before_text: op.before_slice(&before_tokens).concat(),
+                    after_text: op.after_slice(&after_tokens).concat(),
+                }
+            })
+            .collect())
+    }
+
+    /// CSS-like paths of the elements enclosing the token at each of the
+    /// ascending `positions`, starting from `root`
+    fn element_paths(
+        &self,
+        root: Option<String>,
+        tokens: &[String],
+        positions: impl Iterator<Item = usize>,
+    ) -> Vec<String> {
+        let mut open: Vec<(String, String)> = root
+            .into_iter()
+            .map(|selector| ("body".to_string(), selector))
+            .collect();
+        let mut paths = Vec::new();
+        let mut walked = 0;
+        for position in positions {
+            // Line and block tokens group text with the tags inside it, so
+            // they are walked word by word
+            let words = tokens[walked..position.min(tokens.len())]
+                .iter()
+                .flat_map(|token| {
+                    if self.is_tag(token) || self.is_unit(token) || !token.contains('<') {
+                        vec![token.clone()]
+                    } else {
+                        self.word_tokens(token)
+                    }
+                });
+            for token in words {
+                if self.is_unit(&token) || !self.is_tag(&token) {
+                    continue;
+                }
+                let Some(tag) = Tag::parse(&token) else {
+                    continue;
+                };
+                if tag.closing {
+                    if let Some(index) = open.iter().rposition(|(name, _)| *name == tag.name) {
+                        open.truncate(index);
+                    }
+                } else if !VOID_ELEMENTS.contains(&tag.name.as_str()) && !token.ends_with("/>") {
+                    open.push((tag.name.clone(), tag.selector()));
+                }
+            }
+            walked = walked.max(position.min(tokens.len()));
+            let path: Vec<&str> = open.iter().map(|(_, selector)| selector.as_str()).collect();
+            paths.push(path.join(">"));
         }
+        paths
+    }
+
+    /// Summarizes how much changed between two documents
+    pub fn stats(&self, before: &str, after: &str) -> DiffStats {
+        DiffStats::from_operations(&self.operations(before, after))
+    }
+
+    /// Merges `ours` and `theirs`, two independent edits of `base`. Edits
+    /// that don't overlap are applied together; overlapping edits are
+    /// rendered as `class="diff-conflict"` regions holding both variants,
+    /// widened until neither variant splits an element across the region
+    /// boundary.
+    ///
+    /// If the configured [`Limits`] are exceeded even after any fallback,
+    /// both inputs are returned as a single conflict.
+    pub fn merge3(&self, base: &str, ours: &str, theirs: &str) -> MergeResult {
+        self.try_merge3(base, ours, theirs)
+            .unwrap_or_else(|_| MergeResult {
+                html: merge::conflict(
+                    &self.sanitized(normalize(ours)),
+                    &self.sanitized(normalize(theirs)),
+                    true,
+                ),
+                has_conflicts: true,
+                conflicts: 1,
+            })
+    }
+
+    /// Like [`HtmlDiff::merge3`], but fails instead of falling back when the
+    /// configured [`Limits`] are exceeded
+    pub fn try_merge3(
+        &self,
+        base: &str,
+        ours: &str,
+        theirs: &str,
+    ) -> Result<MergeResult, DiffError> {
+        merge::merge3(self, base, ours, theirs)
+    }
+
+    /// Turns diff markup produced by [`HtmlDiff::diff`] back into the
+    /// "after" document by keeping every insertion and dropping every
+    /// deletion.
+    ///
+    /// Inserted and deleted tags are only told apart from unchanged ones
+    /// when the diff was rendered with `mark_tag_changes`, so
+    /// `accept_all(&diff(a, b)) == b` only holds with that option. Without
+    /// it, both versions of changed tags are left in place. Whitespace and
+    /// entities normalized by the matching modes keep their "after" form,
+    /// and so does the content of `DiffAttributesOnly` elements whose start
+    /// tag is unchanged.
+    pub fn accept_all(&self, diff_html: &str) -> String {
+        patch::apply(self, diff_html, None, Resolution::Accept)
+    }
+
+    /// Turns diff markup produced by [`HtmlDiff::diff`] back into the
+    /// "before" document by dropping every insertion and keeping every
+    /// deletion. See [`HtmlDiff::accept_all`] for the limitations.
+    pub fn reject_all(&self, diff_html: &str) -> String {
+        patch::apply(self, diff_html, None, Resolution::Reject)
+    }
+
+    /// Accepts a single change, leaving the rest of the diff markup as it
+    /// is. When the diff was rendered with `change_ids`, `id` is the
+    /// `data-diff-id` of the change. Otherwise changes are numbered from 0
+    /// in document order: every insertion, deletion, group of marked tags,
+    /// formatting change and changed link counts as one.
+    pub fn accept(&self, diff_html: &str, id: usize) -> String {
+        patch::apply(self, diff_html, Some(id), Resolution::Accept)
+    }
+
+    /// Rejects a single change, leaving the rest of the diff markup as it
+    /// is. Changes are numbered as in [`HtmlDiff::accept`].
+    pub fn reject(&self, diff_html: &str, id: usize) -> String {
+        patch::apply(self, diff_html, Some(id), Resolution::Reject)
+    }
+
+    /// An input reduced to the allowlist of the `sanitize` option, or as it
+    /// is when sanitizing is off
+    pub(crate) fn sanitized<'a>(&self, html: &'a str) -> Cow<'a, str> {
+        match &self.options.sanitize {
+            Some(options) => Cow::Owned(sanitize::sanitize(html, options)),
+            None => Cow::Borrowed(html),
+        }
+    }
+
+    pub(crate) fn check_input_size(&self, before: &str, after: &str) -> Result<(), DiffError> {
+        self.check_size("before", before)?;
+        self.check_size("after", after)
+    }
+
+    /// Checks one input, named `field` in errors, against `max_input_bytes`
+    pub(crate) fn check_size(&self, field: &str, html: &str) -> Result<(), DiffError> {
+        match self.options.limits.max_input_bytes {
+            Some(limit) if html.len() > limit => Err(DiffError::SizeLimitExceeded {
+                field: field.to_string(),
+                limit,
+                actual: html.len(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the token count and approximate memory use of both inputs
+    fn check_token_limits(&self, before: &[String], after: &[String]) -> Result<(), DiffError> {
+        let limits = &self.options.limits;
+        if let Some(limit) = limits.max_tokens {
+            for (field, tokens) in [("before", before), ("after", after)] {
+                if tokens.len() > limit {
+                    return Err(DiffError::TokenLimitExceeded {
+                        field: field.to_string(),
+                        limit,
+                        actual: tokens.len(),
+                    });
+                }
+            }
+        }
+        if let Some(limit) = limits.max_memory_bytes {
+            // Each token is held once as text and once as a comparison key,
+            // plus an index entry per "after" token. The LCS algorithm also
+            // fills a table with a cell for every pair of tokens.
+            let mut estimate: usize = before
+                .iter()
+                .chain(after)
+                .map(|token| 2 * (token.len() + std::mem::size_of::<String>()))
+                .sum::<usize>()
+                + after.len() * std::mem::size_of::<usize>();
+            if self.options.algorithm == Algorithm::Lcs {
+                estimate = estimate.saturating_add(
+                    (before.len() + 1)
+                        .saturating_mul(after.len() + 1)
+                        .saturating_mul(std::mem::size_of::<u32>()),
+                );
+            }
+            if estimate > limit {
+                return Err(DiffError::MemoryLimitExceeded { limit, estimate });
+            }
+        }
+        Ok(())
+    }
+
+    /// Merges each run of text between tags into a single token
+    pub(crate) fn coarse_tokens(&self, tokens: Vec<String>) -> Vec<String> {
+        let mut coarse: Vec<String> = Vec::new();
+        let mut in_text = false;
+        for token in tokens {
+            let is_text = !self.is_tag(&token) && !self.is_unit(&token);
+            match coarse.last_mut() {
+                Some(last) if is_text && in_text => last.push_str(&token),
+                _ => coarse.push(token),
+            }
+            in_text = is_text;
+        }
+        coarse
+    }
+
+    /// Tokenizes both inputs and calculates the operations between them
+    /// within the configured limits, retrying with coarse tokens if allowed
+    fn plan(&self, before: &str, after: &str) -> Result<Plan, DiffError> {
+        let limits = &self.options.limits;
+        let budget = Budget::new(limits);
 
-        // Handle HTML content regardless of delimiters
         let before_tokens = self.html_to_tokens(before);
         let after_tokens = self.html_to_tokens(after);
-        let operations = self.calculate_operations(&before_tokens, &after_tokens);
+        let attempt = self
+            .check_token_limits(&before_tokens, &after_tokens)
+            .and_then(|_| self.calculate_operations(&before_tokens, &after_tokens, &budget));
+
+        match attempt {
+            Ok(operations) => Ok(Plan {
+                before_tokens,
+                after_tokens,
+                operations,
+            }),
+            Err(_) if limits.fallback == LimitFallback::Coarse => {
+                let before_tokens = self.coarse_tokens(before_tokens);
+                let after_tokens = self.coarse_tokens(after_tokens);
+                self.check_token_limits(&before_tokens, &after_tokens)?;
+                budget.reset_iterations();
+                let operations =
+                    self.calculate_operations(&before_tokens, &after_tokens, &budget)?;
+                Ok(Plan {
+                    before_tokens,
+                    after_tokens,
+                    operations,
+                })
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Extracts the visible text of a token list as runs of words,
+    /// whitespace and block boundaries, skipping scripts, styles and media
+    fn text_runs(&self, tokens: &[String]) -> Vec<TextRun> {
+        let mut runs: Vec<TextRun> = Vec::new();
+        let mut hidden: Option<String> = None;
+
+        for (i, token) in tokens.iter().enumerate() {
+            if let Some(name) = &hidden {
+                if Tag::parse(token).is_some_and(|tag| tag.closing && tag.name == *name) {
+                    hidden = None;
+                }
+                continue;
+            }
+            if self.is_unit(token) {
+                continue;
+            }
+            if self.is_tag(token) {
+                match Tag::parse(token) {
+                    Some(tag) if !tag.closing && HIDDEN_ELEMENTS.contains(&tag.name.as_str()) => {
+                        hidden = Some(tag.name);
+                    }
+                    Some(tag) if BLOCK_ELEMENTS.contains(&tag.name.as_str()) => {
+                        if runs.last().is_some_and(|run| run.key == " ") {
+                            runs.pop();
+                        }
+                        if runs.last().is_some_and(|run| run.key != "\n") {
+                            runs.push(TextRun {
+                                key: "\n".to_string(),
+                                tokens: i..i,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            let key = if token.chars().all(|c| c.is_whitespace()) {
+                if runs
+                    .last()
+                    .is_none_or(|run| run.key == "\n" || run.key == " ")
+                {
+                    continue;
+                }
+                " ".to_string()
+            } else if token.starts_with('&') {
+                options::decode_entity(token).map_or_else(|| token.clone(), String::from)
+            } else {
+                token.clone()
+            };
+            runs.push(TextRun {
+                key,
+                tokens: i..i + 1,
+            });
+        }
+
+        while runs
+            .last()
+            .is_some_and(|run| run.key == " " || run.key == "\n")
+        {
+            runs.pop();
+        }
+        runs
+    }
+
+    /// Merges runs of text that aren't separated by tags into a single run
+    fn coarse_runs(&self, runs: Vec<TextRun>) -> Vec<TextRun> {
+        let mut coarse: Vec<TextRun> = Vec::new();
+        for run in runs {
+            match coarse.last_mut() {
+                Some(last)
+                    if last.key != "\n"
+                        && run.key != "\n"
+                        && last.tokens.end == run.tokens.start =>
+                {
+                    last.key.push_str(&run.key);
+                    last.tokens.end = run.tokens.end;
+                }
+                _ => coarse.push(run),
+            }
+        }
+        coarse
+    }
+
+    /// Tokenizes both inputs and matches their visible text within the
+    /// configured limits, retrying with coarse runs if allowed
+    fn text_plan(&self, before: &str, after: &str) -> Result<TextPlan, DiffError> {
+        let limits = &self.options.limits;
+        let budget = Budget::new(limits);
+
+        // Blocks mix markup into the text and lines follow the source
+        // rather than the visible text, so both fall back to words
+        let tokens = |html: &str| match self.options.granularity {
+            Granularity::Line | Granularity::Block => self.word_tokens(html),
+            _ => self.html_to_tokens(html),
+        };
+        let before_tokens = tokens(before);
+        let after_tokens = tokens(after);
+        self.check_token_limits(&before_tokens, &after_tokens)?;
+
+        let mut before_runs = self.text_runs(&before_tokens);
+        let mut after_runs = self.text_runs(&after_tokens);
+        let keys =
+            |runs: &[TextRun]| -> Vec<String> { runs.iter().map(|run| run.key.clone()).collect() };
+        let operations =
+            match self.calculate_operations(&keys(&before_runs), &keys(&after_runs), &budget) {
+                Ok(operations) => operations,
+                Err(_) if limits.fallback == LimitFallback::Coarse => {
+                    before_runs = self.coarse_runs(before_runs);
+                    after_runs = self.coarse_runs(after_runs);
+                    budget.reset_iterations();
+                    self.calculate_operations(&keys(&before_runs), &keys(&after_runs), &budget)?
+                }
+                Err(err) => return Err(err),
+            };
+
+        Ok(TextPlan {
+            before_tokens,
+            after_tokens,
+            before_runs,
+            after_runs,
+            operations,
+        })
+    }
+
+    /// Renders a text mode plan onto the markup of the "after" input.
+    /// Inserted text is marked where it appears, deleted text is placed
+    /// where it would have been, and all other tokens are kept as they are.
+    fn render_text_plan<W: fmt::Write>(&self, plan: &TextPlan, out: &mut W) -> fmt::Result {
+        let TextPlan {
+            before_tokens,
+            after_tokens,
+            before_runs,
+            after_runs,
+            operations,
+        } = plan;
+        // "After" tokens written so far
+        let mut written = 0;
+        let mut changes_seen = 0;
 
-        let mut rendering = String::new();
         for op in operations {
+            let change = ChangeId {
+                id: changes_seen,
+                action: op.action,
+            };
+            if op.action != Operation::Equal {
+                changes_seen += 1;
+            }
+
+            let deleted: String = match op.action {
+                Operation::Delete | Operation::Replace => op.before_slice(before_runs),
+                Operation::Equal | Operation::Insert => &[],
+            }
+            .iter()
+            .flat_map(|run| &before_tokens[run.tokens.clone()])
+            .map(String::as_str)
+            .collect();
+            if !deleted.is_empty() {
+                if written == 0 {
+                    // Keep deletions at the start inside the first element
+                    let start = after_runs.first().map_or(0, |run| run.tokens.start);
+                    out.write_str(&after_tokens[..start].concat())?;
+                    written = start;
+                }
+                out.write_str(&self.marker_start(false, None, change))?;
+                out.write_str(&deleted)?;
+                out.write_str(&self.marker_end(false))?;
+            }
+
+            let insert = matches!(op.action, Operation::Insert | Operation::Replace);
+            let mut open = false;
+            for run in op.after_slice(after_runs) {
+                if run.tokens.is_empty() {
+                    continue;
+                }
+                if written < run.tokens.start {
+                    if open {
+                        out.write_str(&self.marker_end(true))?;
+                        open = false;
+                    }
+                    out.write_str(&after_tokens[written..run.tokens.start].concat())?;
+                }
+                if insert && !open {
+                    out.write_str(&self.marker_start(true, None, change))?;
+                    open = true;
+                }
+                out.write_str(&after_tokens[run.tokens.clone()].concat())?;
+                written = run.tokens.end;
+            }
+            if open {
+                out.write_str(&self.marker_end(true))?;
+            }
+        }
+
+        out.write_str(&after_tokens[written..].concat())
+    }
+
+    /// Renders the operations of a plan, returning the structured changes
+    /// found along the way. The `html` field of the result is left empty.
+    fn render_plan<W: fmt::Write>(
+        &self,
+        plan: &Plan,
+        out: &mut W,
+    ) -> Result<DiffResult, fmt::Error> {
+        let Plan {
+            before_tokens,
+            after_tokens,
+            operations,
+        } = plan;
+        let mut format_spans = self.pair_format_changes(operations, before_tokens, after_tokens);
+
+        let mut attribute_changes = Vec::new();
+        let mut format_changes = Vec::new();
+        let mut changes_seen = 0;
+        for (i, op) in operations.iter().enumerate() {
+            let change = ChangeId {
+                id: changes_seen,
+                action: op.action,
+            };
+            let span = format_spans.remove(&i);
+            // The operation closing a formatting change belongs to the change
+            // its opening operation started
+            if op.action != Operation::Equal && !matches!(span, Some(FormatSpan::Close)) {
+                changes_seen += 1;
+            }
+
+            match span {
+                Some(FormatSpan::Open(format_change)) => {
+                    write!(
+                        out,
+                        "<span class=\"diff-format\" data-diff-format=\"{}\"",
+                        format_change.marker()
+                    )?;
+                    if self.options.change_ids {
+                        for (name, value) in change.attributes() {
+                            write!(out, " {}=\"{}\"", name, value)?;
+                        }
+                    }
+                    out.write_char('>')?;
+                    out.write_str(&op.after_slice(after_tokens).concat())?;
+                    format_changes.push(format_change);
+                    continue;
+                }
+                Some(FormatSpan::Close) => {
+                    out.write_str(&op.after_slice(after_tokens).concat())?;
+                    out.write_str("</span>")?;
+                    continue;
+                }
+                None => {}
+            }
+
             match op.action {
                 Operation::Equal => {
-                    rendering.push_str(
-                        &before_tokens[op.start_in_before..=op.end_in_before.unwrap()].join(""),
-                    );
+                    // Equal tokens may still differ under the whitespace and
+                    // entity modes, so the "after" side is kept
+                    for token in op.after_slice(after_tokens) {
+                        out.write_str(token)?;
+                    }
                 }
                 Operation::Insert => {
-                    let val = &after_tokens[op.start_in_after..=op.end_in_after.unwrap()];
-                    rendering.push_str(&self.wrap("ins", val));
+                    self.wrap(true, op.after_slice(after_tokens), change, out)?;
                 }
                 Operation::Delete => {
-                    let val = &before_tokens[op.start_in_before..=op.end_in_before.unwrap()];
-                    rendering.push_str(&self.wrap("del", val));
+                    self.wrap(false, op.before_slice(before_tokens), change, out)?;
                 }
                 Operation::Replace => {
-                    let before_val = &before_tokens[op.start_in_before..=op.end_in_before.unwrap()];
-                    let after_val = &after_tokens[op.start_in_after..=op.end_in_after.unwrap()];
-                    rendering.push_str(&self.wrap("del", before_val));
-                    rendering.push_str(&self.wrap("ins", after_val));
+                    let before_val = op.before_slice(before_tokens);
+                    let after_val = op.after_slice(after_tokens);
+                    let mut link_attribute_changes = Vec::new();
+                    match self.link_changes(
+                        before_val,
+                        after_val,
+                        change,
+                        &mut link_attribute_changes,
+                    ) {
+                        Some((before_rest, after_marked)) => {
+                            self.wrap(false, &before_rest, change, out)?;
+                            self.wrap(true, &after_marked, change, out)?;
+                        }
+                        None => {
+                            self.wrap(false, before_val, change, out)?;
+                            self.wrap(true, after_val, change, out)?;
+                        }
+                    }
+                    attribute_changes.extend(link_attribute_changes);
+                    self.media_attribute_changes(before_val, after_val, &mut attribute_changes);
+                }
+            }
+        }
+
+        Ok(DiffResult {
+            attribute_changes,
+            format_changes,
+            ..Default::default()
+        })
+    }
+
+    /// Diffs and renders two inputs into `out`. Everything that can exceed
+    /// the configured limits runs before the first write, so a limit error
+    /// never leaves partial output behind.
+    fn write_compare<W: fmt::Write>(
+        &self,
+        before: &str,
+        after: &str,
+        out: &mut W,
+    ) -> Result<DiffResult, RenderError> {
+        let (before, after) = (normalize(before), normalize(after));
+        self.check_input_size(before, after)?;
+        let (before, after) = (self.sanitized(before), self.sanitized(after));
+        let (before, after) = (before.as_ref(), after.as_ref());
+
+        let before_document = Document::split(before);
+        let after_document = Document::split(after);
+        let before_body = before_document.as_ref().map_or(before, |d| d.body);
+        let after_body = after_document.as_ref().map_or(after, |d| d.body);
+
+        let plan = if before_body == after_body {
+            None
+        } else if self.options.mode == DiffMode::Text {
+            Some(BodyPlan::Text(self.text_plan(before_body, after_body)?))
+        } else {
+            Some(BodyPlan::Markup(self.plan(before_body, after_body)?))
+        };
+
+        let mut head_changes = Vec::new();
+        if before_document.is_some() || after_document.is_some() {
+            head_changes = document::head_changes(
+                before_document.as_ref().map_or("", |d| d.head),
+                after_document.as_ref().map_or("", |d| d.head),
+            );
+        }
+        let (prefix, suffix) = after_document.map_or(("", ""), |d| (d.prefix, d.suffix));
+
+        out.write_str(prefix)?;
+        out.write_str(&document::render_summary(&head_changes))?;
+        let mut result = match &plan {
+            Some(BodyPlan::Markup(plan)) => self.render_plan(plan, out)?,
+            Some(BodyPlan::Text(plan)) => {
+                self.render_text_plan(plan, out)?;
+                DiffResult::default()
+            }
+            None => {
+                out.write_str(after_body)?;
+                DiffResult::default()
+            }
+        };
+        out.write_str(suffix)?;
+
+        result.head_changes = head_changes;
+        Ok(result)
+    }
+
+    /// Renders the fallback for diffs that exceed their limits: the whole
+    /// "before" body as deleted and the whole "after" body as inserted,
+    /// within the rest of the "after" document. Inputs over
+    /// `max_input_bytes` are rejected outright, so nothing is rendered.
+    fn write_replacement<W: fmt::Write>(
+        &self,
+        before: &str,
+        after: &str,
+        out: &mut W,
+    ) -> fmt::Result {
+        let (before, after) = (normalize(before), normalize(after));
+        if self.check_input_size(before, after).is_err() {
+            return Ok(());
+        }
+        let (before, after) = (self.sanitized(before), self.sanitized(after));
+        let (before, after) = (before.as_ref(), after.as_ref());
+
+        let before_body = Document::split(before).map_or(before, |d| d.body);
+        let after_document = Document::split(after);
+        let after_body = after_document.as_ref().map_or(after, |d| d.body);
+        let (prefix, suffix) = after_document.map_or(("", ""), |d| (d.prefix, d.suffix));
+
+        let change = ChangeId {
+            id: 0,
+            action: Operation::Replace,
+        };
+        out.write_str(prefix)?;
+        self.wrap(false, &self.word_tokens(before_body), change, out)?;
+        self.wrap(true, &self.word_tokens(after_body), change, out)?;
+        out.write_str(suffix)
+    }
+
+    /// Streams the diff of two documents into a [`fmt::Write`] sink as
+    /// operations are rendered. Falls back like [`HtmlDiff::diff`] when
+    /// limits are exceeded.
+    pub fn diff_to_fmt<W: fmt::Write>(
+        &self,
+        before: &str,
+        after: &str,
+        out: &mut W,
+    ) -> fmt::Result {
+        match self.write_compare(before, after, out) {
+            Ok(_) => Ok(()),
+            Err(RenderError::Limit(_)) => self.write_replacement(before, after, out),
+            Err(RenderError::Write(err)) => Err(err),
+        }
+    }
+
+    /// Streams the diff of two documents into an [`io::Write`] sink such as
+    /// stdout or a file. Falls back like [`HtmlDiff::diff`] when limits are
+    /// exceeded.
+    pub fn diff_to_writer<W: io::Write>(
+        &self,
+        before: &str,
+        after: &str,
+        writer: W,
+    ) -> io::Result<()> {
+        let mut adapter = IoAdapter::new(writer);
+        match self.diff_to_fmt(before, after, &mut adapter) {
+            Ok(()) => adapter.finish(),
+            Err(_) => Err(adapter.into_error()),
+        }
+    }
+}
+
+/// Failure while writing a diff: either a limit was hit while planning or
+/// the sink refused the output
+enum RenderError {
+    Limit(DiffError),
+    Write(fmt::Error),
+}
+
+impl From<DiffError> for RenderError {
+    fn from(err: DiffError) -> Self {
+        RenderError::Limit(err)
+    }
+}
+
+impl From<fmt::Error> for RenderError {
+    fn from(err: fmt::Error) -> Self {
+        RenderError::Write(err)
+    }
+}
+
+/// Adapts an [`io::Write`] sink to [`fmt::Write`], keeping the underlying
+/// I/O error that `fmt::Error` cannot carry
+pub(crate) struct IoAdapter<W: io::Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> IoAdapter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Flushes the sink once everything has been written
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// The I/O error behind a failed write
+    pub fn into_error(self) -> io::Error {
+        self.error
+            .unwrap_or_else(|| io::Error::other("formatter error"))
+    }
+}
+
+impl<W: io::Write> fmt::Write for IoAdapter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.writer.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
+
+impl Default for HtmlDiff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Normalizes input by stripping surrounding string delimiters
+pub(crate) fn normalize(html: &str) -> &str {
+    html.trim_matches('`').trim_matches('"').trim_matches('\'')
+}
+
+/// The part of an input that is diffed: the `<body>` content of a full
+/// document, or the whole normalized input
+struct DiffedPart<'a> {
+    html: &'a str,
+    /// Byte offset of `html` in the input
+    offset: usize,
+    /// Selector of the `<body>` element of a full document
+    root: Option<String>,
+}
+
+impl<'a> DiffedPart<'a> {
+    fn of(input: &'a str) -> Self {
+        let html = normalize(input);
+        let offset = html.as_ptr() as usize - input.as_ptr() as usize;
+        match Document::split(html) {
+            Some(document) => {
+                let body_tag = &document.prefix[document.prefix.rfind('<').unwrap_or(0)..];
+                Self {
+                    html: document.body,
+                    offset: offset + document.prefix.len(),
+                    root: Some(Tag::parse(body_tag).map_or("body".to_string(), |t| t.selector())),
                 }
             }
+            None => Self {
+                html,
+                offset,
+                root: None,
+            },
         }
+    }
+}
+
+/// Byte offset of each token boundary, starting at `start`
+fn token_offsets(tokens: &[String], start: usize) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(tokens.len() + 1);
+    offsets.push(start);
+    for token in tokens {
+        offsets.push(offsets[offsets.len() - 1] + token.len());
+    }
+    offsets
+}
 
-        rendering
+fn diff_attributes(before: &Tag, after: &Tag, changes: &mut Vec<AttributeChange>) {
+    for (name, value) in &before.attributes {
+        let after_value = after.attribute(name);
+        if after_value != Some(value.as_str()) {
+            changes.push(AttributeChange {
+                element: before.name.clone(),
+                attribute: name.clone(),
+                before: Some(value.clone()),
+                after: after_value.map(str::to_string),
+            });
+        }
+    }
+    for (name, value) in &after.attributes {
+        if before.attribute(name).is_none() {
+            changes.push(AttributeChange {
+                element: after.name.clone(),
+                attribute: name.clone(),
+                before: None,
+                after: Some(value.clone()),
+            });
+        }
+    }
+}
+
+/// Index of the end tag that closes the `name` start tag at `start`, if it
+/// falls within `tokens`
+fn matching_close(tokens: &[String], start: usize, name: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match Tag::parse(token) {
+            Some(tag) if tag.name == name && !tag.closing => depth += 1,
+            Some(tag) if tag.name == name => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
     }
+    None
 }
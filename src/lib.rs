@@ -1,7 +1,40 @@
+mod document;
+pub mod encoding;
+pub use encoding::{decode_bytes, detect_charset, encode_string, Charset};
+pub mod error;
+pub use error::DiffError;
 pub mod htmldiff;
-pub use htmldiff::HtmlDiff;
+pub use document::HeadChange;
+pub use htmldiff::{AttributeChange, ChangeKind, DiffOp, DiffResult, FormatChange, HtmlDiff};
+pub mod merge;
+pub use merge::MergeResult;
+pub mod options;
+pub use options::{
+    Algorithm, DiffMode, ElementStrategy, EntityMode, Granularity, HtmlDiffOptions, LimitFallback,
+    Limits, WhitespaceMode,
+};
+pub mod page;
+pub use page::{render_page, PageOptions, Theme};
+mod patch;
+pub mod render;
+pub use render::{
+    render_ansi, render_json, render_markdown, render_text, JsonDiff, JSON_SCHEMA_VERSION,
+};
+pub mod sanitize;
+pub use sanitize::{sanitize, SanitizeOptions};
+pub mod stats;
+pub use stats::DiffStats;
 #[cfg(feature = "extism")]
 pub mod plugin;
+mod tag;
+pub mod tokenizer;
+pub use tokenizer::{DefaultTokenPolicy, Token, TokenKind, TokenPolicy, Tokenizer};
+pub mod unified;
+pub use unified::{try_unified_diff, unified_diff, UnifiedMode, UnifiedOptions};
+
+use htmldiff::IoAdapter;
+use std::fmt::{self, Write as _};
+use std::io;
 
 /// Restores HTML content from diff format using HtmlDiff
 ///
@@ -11,18 +44,42 @@ pub mod plugin;
 /// # Returns
 /// A string with the restored HTML content
 pub fn restore_from_diff(diff_content: &str) -> String {
-    let lines: Vec<&str> = diff_content.split('\n').collect();
     let mut result = String::new();
+    // Writing to a String cannot fail
+    let _ = restore_from_diff_to_fmt(diff_content, &mut result);
+    result
+}
+
+/// Streams restored HTML content into an [`io::Write`] sink, one hunk at a time
+///
+/// # Arguments
+/// * `diff_content` - A string containing the diff content
+/// * `writer` - Where the restored HTML content is written
+pub fn restore_from_diff_to_writer<W: io::Write>(diff_content: &str, writer: W) -> io::Result<()> {
+    let mut adapter = IoAdapter::new(writer);
+    match restore_from_diff_to_fmt(diff_content, &mut adapter) {
+        Ok(()) => adapter.finish(),
+        Err(_) => Err(adapter.into_error()),
+    }
+}
+
+/// Streams restored HTML content into a [`fmt::Write`] sink, one hunk at a time
+///
+/// # Arguments
+/// * `diff_content` - A string containing the diff content
+/// * `out` - Where the restored HTML content is written
+pub fn restore_from_diff_to_fmt<W: fmt::Write>(diff_content: &str, out: &mut W) -> fmt::Result {
+    let mut out = Trimmed::new(out);
     let mut deletions: Vec<String> = Vec::new();
     let mut insertions: Vec<String> = Vec::new();
     let diff = HtmlDiff::new();
 
-    fn process_changes(
+    fn process_changes<W: fmt::Write>(
         diff: &HtmlDiff,
-        result: &mut String,
+        out: &mut W,
         deletions: &mut Vec<String>,
         insertions: &mut Vec<String>,
-    ) {
+    ) -> fmt::Result {
         let max_len = std::cmp::max(deletions.len(), insertions.len());
         for i in 0..max_len {
             let before = if i < deletions.len() {
@@ -37,34 +94,79 @@ pub fn restore_from_diff(diff_content: &str) -> String {
             } else {
                 String::new()
             };
-            result.push_str(&diff.diff(&before, &after));
-            result.push('\n');
+            diff.diff_to_fmt(&before, &after, out)?;
+            out.write_char('\n')?;
         }
         deletions.clear();
         insertions.clear();
+        Ok(())
     }
 
-    for line in lines {
-        if line.starts_with(' ') {
+    let lines: Vec<&str> = diff_content.split('\n').collect();
+    for (i, line) in lines.iter().enumerate() {
+        // File headers are not content
+        let is_header = (line.starts_with("--- ")
+            && lines
+                .get(i + 1)
+                .is_some_and(|next| next.starts_with("+++ ")))
+            || (line.starts_with("+++ ") && i > 0 && lines[i - 1].starts_with("--- "));
+        if is_header {
+            continue;
+        }
+
+        if let Some(context) = line.strip_prefix(' ') {
             // Context line, process any pending changes
-            process_changes(&diff, &mut result, &mut deletions, &mut insertions);
-            result.push_str(&line[1..]);
-            result.push('\n');
-        } else if line.starts_with('-') {
+            process_changes(&diff, &mut out, &mut deletions, &mut insertions)?;
+            out.write_str(context)?;
+            out.write_char('\n')?;
+        } else if let Some(deleted) = line.strip_prefix('-') {
             // Deletion line - strip any existing HTML tags
-            let content = line[1..].replace("<del>", "").replace("</del>", "");
+            let content = deleted.replace("<del>", "").replace("</del>", "");
             deletions.push(content);
-        } else if line.starts_with('+') {
+        } else if let Some(inserted) = line.strip_prefix('+') {
             // Addition line - strip any existing HTML tags
-            let content = line[1..].replace("<ins>", "").replace("</ins>", "");
+            let content = inserted.replace("<ins>", "").replace("</ins>", "");
             insertions.push(content);
         }
     }
 
     // Process any remaining changes
-    process_changes(&diff, &mut result, &mut deletions, &mut insertions);
+    process_changes(&diff, &mut out, &mut deletions, &mut insertions)
+}
 
-    result.trim().to_string()
+/// Writer that drops leading and trailing whitespace from everything
+/// written through it, holding back whitespace until more content follows
+struct Trimmed<'a, W: fmt::Write> {
+    out: &'a mut W,
+    started: bool,
+    pending: String,
+}
+
+impl<'a, W: fmt::Write> Trimmed<'a, W> {
+    fn new(out: &'a mut W) -> Self {
+        Self {
+            out,
+            started: false,
+            pending: String::new(),
+        }
+    }
+}
+
+impl<W: fmt::Write> fmt::Write for Trimmed<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let s = if self.started { s } else { s.trim_start() };
+        let content = s.trim_end();
+        if !content.is_empty() {
+            self.started = true;
+            self.out.write_str(&self.pending)?;
+            self.pending.clear();
+            self.out.write_str(content)?;
+        }
+        if self.started {
+            self.pending.push_str(&s[content.len()..]);
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
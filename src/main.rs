@@ -1 +1,244 @@
-fn main() {}
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+use diff_html_rs::{
+    render_ansi, render_json, render_markdown, render_page, render_text, unified_diff, DiffMode,
+    DiffOp, ElementStrategy, Granularity, HtmlDiff, HtmlDiffOptions, PageOptions, SanitizeOptions,
+    Theme, UnifiedMode, UnifiedOptions,
+};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+/// Compare two HTML files and print the diff with <ins> and <del> markup
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    /// The original HTML file
+    before: PathBuf,
+
+    /// The modified HTML file
+    after: PathBuf,
+
+    /// Write the diff to a file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Print a summary of how much changed instead of the diff
+    #[arg(long)]
+    stat: bool,
+
+    /// Output format of the diff
+    #[arg(long, value_enum, default_value_t = Format::Html)]
+    format: Format,
+
+    /// Compare only the visible text, ignoring changes to markup. Only
+    /// applies to HTML output.
+    #[arg(long, conflicts_with = "stat")]
+    text_only: bool,
+
+    /// Smallest unit a change can cover
+    #[arg(long, value_enum, default_value_t = CliGranularity::Word)]
+    granularity: CliGranularity,
+
+    /// How to diff an element, as NAME=atomic|diff-children|diff-attributes-only;
+    /// may be repeated
+    #[arg(long = "element", value_name = "NAME=STRATEGY", value_parser = parse_element)]
+    elements: Vec<(String, CliElementStrategy)>,
+
+    /// Strip scripts, event handlers, unsafe URLs and unknown tags from both
+    /// inputs, for displaying diffs of untrusted HTML. Text formats decode
+    /// entities, so their output is only safe to display as text.
+    #[arg(long)]
+    sanitize: bool,
+
+    /// Unchanged lines shown around each change in unified diffs
+    #[arg(long, default_value_t = 3)]
+    context: usize,
+
+    /// Wrap the diff in a complete HTML page with an embedded stylesheet
+    #[arg(long)]
+    standalone: bool,
+
+    /// Colour theme of the standalone page
+    #[arg(long, value_enum, default_value_t = CliTheme::Light)]
+    theme: CliTheme,
+
+    /// Title of the standalone page
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Leave out the legend on the standalone page
+    #[arg(long)]
+    no_legend: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    /// Markup with <ins> and <del> tags
+    Html,
+    /// The list of diff operations as versioned JSON
+    Json,
+    /// Plain text with [-deleted-] and {+inserted+} markers
+    Text,
+    /// Markdown with ~~deleted~~ and **inserted** text
+    Markdown,
+    /// Text coloured for a terminal
+    Ansi,
+    /// Unified diff of the pretty-printed markup
+    Unified,
+    /// Unified diff of the visible text
+    UnifiedText,
+}
+
+impl Format {
+    /// Renderer of the operation list, or `None` for HTML, which is
+    /// streamed as it is rendered, and unified diffs, which are line based
+    fn renderer(self) -> Option<fn(&[DiffOp]) -> String> {
+        match self {
+            Format::Html | Format::Unified | Format::UnifiedText => None,
+            Format::Json => Some(render_json),
+            Format::Text => Some(render_text),
+            Format::Markdown => Some(render_markdown),
+            Format::Ansi => Some(render_ansi),
+        }
+    }
+
+    fn unified_mode(self) -> Option<UnifiedMode> {
+        match self {
+            Format::Unified => Some(UnifiedMode::Pretty),
+            Format::UnifiedText => Some(UnifiedMode::Text),
+            _ => None,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum CliGranularity {
+    Character,
+    Word,
+    Sentence,
+    Line,
+    Block,
+}
+
+impl From<CliGranularity> for Granularity {
+    fn from(granularity: CliGranularity) -> Self {
+        match granularity {
+            CliGranularity::Character => Granularity::Character,
+            CliGranularity::Word => Granularity::Word,
+            CliGranularity::Sentence => Granularity::Sentence,
+            CliGranularity::Line => Granularity::Line,
+            CliGranularity::Block => Granularity::Block,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum CliElementStrategy {
+    Atomic,
+    DiffChildren,
+    DiffAttributesOnly,
+}
+
+impl From<CliElementStrategy> for ElementStrategy {
+    fn from(strategy: CliElementStrategy) -> Self {
+        match strategy {
+            CliElementStrategy::Atomic => ElementStrategy::Atomic,
+            CliElementStrategy::DiffChildren => ElementStrategy::DiffChildren,
+            CliElementStrategy::DiffAttributesOnly => ElementStrategy::DiffAttributesOnly,
+        }
+    }
+}
+
+/// Parses a `NAME=STRATEGY` element argument
+fn parse_element(arg: &str) -> Result<(String, CliElementStrategy), String> {
+    let (name, strategy) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=STRATEGY, got `{}`", arg))?;
+    let strategy = CliElementStrategy::from_str(strategy, true)?;
+    Ok((name.to_ascii_lowercase(), strategy))
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum CliTheme {
+    Light,
+    Dark,
+    Auto,
+}
+
+impl From<CliTheme> for Theme {
+    fn from(theme: CliTheme) -> Self {
+        match theme {
+            CliTheme::Light => Theme::Light,
+            CliTheme::Dark => Theme::Dark,
+            CliTheme::Auto => Theme::Auto,
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    if cli.text_only && !matches!(cli.format, Format::Html) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--text-only only applies to --format html",
+            )
+            .exit();
+    }
+
+    let before = fs::read_to_string(&cli.before)?;
+    let after = fs::read_to_string(&cli.after)?;
+
+    let mut writer: Box<dyn Write> = match &cli.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        mode: if cli.text_only {
+            DiffMode::Text
+        } else {
+            DiffMode::Markup
+        },
+        granularity: cli.granularity.into(),
+        elements: cli
+            .elements
+            .iter()
+            .map(|(name, strategy)| (name.clone(), (*strategy).into()))
+            .collect(),
+        sanitize: cli.sanitize.then(SanitizeOptions::default),
+        ..Default::default()
+    });
+    if cli.stat {
+        writeln!(writer, "{}", diff.stats(&before, &after))?;
+    } else if let Some(mode) = cli.format.unified_mode() {
+        let options = UnifiedOptions {
+            context: cli.context,
+            mode,
+            before_label: cli.before.display().to_string(),
+            after_label: cli.after.display().to_string(),
+        };
+        writer.write_all(unified_diff(&diff, &before, &after, &options).as_bytes())?;
+    } else if let Some(render) = cli.format.renderer() {
+        writeln!(writer, "{}", render(&diff.operations(&before, &after)))?;
+    } else if cli.standalone {
+        let defaults = PageOptions::default();
+        let page = render_page(
+            &diff.diff(&before, &after),
+            &PageOptions {
+                title: cli.title.unwrap_or(defaults.title),
+                theme: cli.theme.into(),
+                legend: !cli.no_legend,
+            },
+        );
+        writer.write_all(page.as_bytes())?;
+    } else {
+        diff.diff_to_writer(&before, &after, &mut writer)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use crate::error::DiffError;
+use crate::htmldiff::{normalize, HtmlDiff, VOID_ELEMENTS};
+use crate::options::LimitFallback;
+use crate::render::BLOCK_ELEMENTS;
+use crate::tag::Tag;
+use serde::Serialize;
+use std::ops::Range;
+
+/// Outcome of a three-way merge
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MergeResult {
+    /// Merged markup, with overlapping edits rendered as
+    /// `class="diff-conflict"` regions holding both variants: `<div>`
+    /// elements around blocks and `<span>` elements within text
+    pub html: String,
+    /// Whether any conflict regions remain in `html`
+    pub has_conflicts: bool,
+    /// Number of conflict regions in `html`
+    pub conflicts: usize,
+}
+
+/// A run of tokens left untouched by both sides, as
+/// `(start_in_base, start_in_ours, start_in_theirs, length)`
+type SyncRegion = (usize, usize, usize, usize);
+
+/// Merges `ours` and `theirs`, two independent edits of `base`
+pub(crate) fn merge3(
+    hd: &HtmlDiff,
+    base: &str,
+    ours: &str,
+    theirs: &str,
+) -> Result<MergeResult, DiffError> {
+    let (base, ours, theirs) = (normalize(base), normalize(ours), normalize(theirs));
+    hd.check_input_size(base, ours)?;
+    hd.check_input_size(base, theirs)?;
+
+    let base_tokens = hd.html_to_tokens(&hd.sanitized(base));
+    let ours_tokens = hd.html_to_tokens(&hd.sanitized(ours));
+    let theirs_tokens = hd.html_to_tokens(&hd.sanitized(theirs));
+    match merge_tokens(hd, &base_tokens, &ours_tokens, &theirs_tokens) {
+        Err(_) if hd.options().limits.fallback == LimitFallback::Coarse => merge_tokens(
+            hd,
+            &hd.coarse_tokens(base_tokens),
+            &hd.coarse_tokens(ours_tokens),
+            &hd.coarse_tokens(theirs_tokens),
+        ),
+        result => result,
+    }
+}
+
+/// A stretch of the three inputs between or at sync regions, as token
+/// ranges into base, ours and theirs
+#[derive(Debug, Clone)]
+struct Hunk {
+    base: Range<usize>,
+    ours: Range<usize>,
+    theirs: Range<usize>,
+    conflict: bool,
+}
+
+fn merge_tokens(
+    hd: &HtmlDiff,
+    base: &[String],
+    ours: &[String],
+    theirs: &[String],
+) -> Result<MergeResult, DiffError> {
+    let regions = sync_regions(
+        &hd.matching_runs(base, ours)?,
+        &hd.matching_runs(base, theirs)?,
+    );
+    let same = |a: &[String], b: &[String]| {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b)
+                .all(|(a, b)| hd.comparison_key(a) == hd.comparison_key(b))
+    };
+
+    let mut hunks = Vec::new();
+    let (mut in_base, mut in_ours, mut in_theirs) = (0, 0, 0);
+    let end = (base.len(), ours.len(), theirs.len(), 0);
+    for (start_in_base, start_in_ours, start_in_theirs, length) in regions.into_iter().chain([end])
+    {
+        let changed = Hunk {
+            base: in_base..start_in_base,
+            ours: in_ours..start_in_ours,
+            theirs: in_theirs..start_in_theirs,
+            conflict: false,
+        };
+        let (base_chunk, ours_chunk, theirs_chunk) = (
+            &base[changed.base.clone()],
+            &ours[changed.ours.clone()],
+            &theirs[changed.theirs.clone()],
+        );
+        let conflict = !same(base_chunk, ours_chunk)
+            && !same(base_chunk, theirs_chunk)
+            && !same(ours_chunk, theirs_chunk);
+        hunks.push(Hunk {
+            conflict,
+            ..changed
+        });
+
+        in_base = start_in_base + length;
+        in_ours = start_in_ours + length;
+        in_theirs = start_in_theirs + length;
+        hunks.push(Hunk {
+            base: start_in_base..in_base,
+            ours: start_in_ours..in_ours,
+            theirs: start_in_theirs..in_theirs,
+            conflict: false,
+        });
+    }
+    let hunks = widen_conflicts(hunks, ours, theirs);
+
+    let mut result = MergeResult::default();
+    for hunk in hunks {
+        let (base_chunk, ours_chunk, theirs_chunk) =
+            (&base[hunk.base], &ours[hunk.ours], &theirs[hunk.theirs]);
+        if hunk.conflict {
+            let block = ours_chunk
+                .iter()
+                .chain(theirs_chunk)
+                .any(|t| is_block_tag(t));
+            result.html.push_str(&conflict(
+                &ours_chunk.concat(),
+                &theirs_chunk.concat(),
+                block,
+            ));
+            result.conflicts += 1;
+        } else if same(base_chunk, ours_chunk) {
+            result.html.push_str(&theirs_chunk.concat());
+        } else {
+            result.html.push_str(&ours_chunk.concat());
+        }
+    }
+
+    result.has_conflicts = result.conflicts > 0;
+    Ok(result)
+}
+
+/// Grows each conflict over its neighbouring hunks until both of its
+/// variants close every element they open and open every element they
+/// close, so that a conflict region never splits a start tag from its end
+/// tag. Conflicts that grow into each other are joined.
+fn widen_conflicts(hunks: Vec<Hunk>, ours: &[String], theirs: &[String]) -> Vec<Hunk> {
+    let mut widened: Vec<Hunk> = Vec::with_capacity(hunks.len());
+    let mut rest = hunks.into_iter().peekable();
+    while let Some(mut hunk) = rest.next() {
+        if !hunk.conflict {
+            widened.push(hunk);
+            continue;
+        }
+        loop {
+            let (ours_open, ours_close) = unbalanced(&ours[hunk.ours.clone()]);
+            let (theirs_open, theirs_close) = unbalanced(&theirs[hunk.theirs.clone()]);
+            if ours_close || theirs_close {
+                if let Some(previous) = widened.pop() {
+                    hunk = join(previous, hunk);
+                    continue;
+                }
+            }
+            if ours_open || theirs_open {
+                if let Some(next) = rest.next() {
+                    hunk = join(hunk, next);
+                    continue;
+                }
+            }
+            break;
+        }
+        // Joining may have reached an earlier conflict
+        match widened.last_mut() {
+            Some(previous) if previous.conflict && previous.ours.end == hunk.ours.start => {
+                *previous = join(previous.clone(), hunk);
+            }
+            _ => widened.push(hunk),
+        }
+    }
+    widened
+}
+
+/// Joins two adjacent hunks into a conflict covering both
+fn join(first: Hunk, second: Hunk) -> Hunk {
+    Hunk {
+        base: first.base.start..second.base.end,
+        ours: first.ours.start..second.ours.end,
+        theirs: first.theirs.start..second.theirs.end,
+        conflict: true,
+    }
+}
+
+/// Whether a token run leaves elements open, and whether it closes
+/// elements it did not open
+fn unbalanced(tokens: &[String]) -> (bool, bool) {
+    let mut open: Vec<String> = Vec::new();
+    let mut closes_outer = false;
+    for tag in tokens.iter().filter_map(|token| Tag::parse(token)) {
+        if VOID_ELEMENTS.contains(&tag.name.as_str()) {
+            continue;
+        }
+        if !tag.closing {
+            open.push(tag.name);
+        } else if let Some(i) = open.iter().rposition(|name| *name == tag.name) {
+            open.truncate(i);
+        } else {
+            closes_outer = true;
+        }
+    }
+    (!open.is_empty(), closes_outer)
+}
+
+/// Whether a token is a tag of an element that cannot sit inside a
+/// paragraph
+fn is_block_tag(token: &str) -> bool {
+    Tag::parse(token)
+        .is_some_and(|tag| tag.name != "br" && BLOCK_ELEMENTS.contains(&tag.name.as_str()))
+}
+
+/// Renders a conflict region holding both variants, as `<div>` elements
+/// when the variants hold blocks and as `<span>` elements within text
+pub(crate) fn conflict(ours: &str, theirs: &str, block: bool) -> String {
+    let element = if block { "div" } else { "span" };
+    format!(
+        "<{0} class=\"diff-conflict\">\
+         <{0} class=\"diff-conflict-ours\">{1}</{0}>\
+         <{0} class=\"diff-conflict-theirs\">{2}</{0}>\
+         </{0}>",
+        element, ours, theirs
+    )
+}
+
+/// Intersects the base ranges matched by each side, keeping the runs of
+/// base tokens that both sides left in place
+fn sync_regions(
+    ours: &[(usize, usize, usize)],
+    theirs: &[(usize, usize, usize)],
+) -> Vec<SyncRegion> {
+    let mut regions = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < ours.len() && j < theirs.len() {
+        let (ours_base, ours_start, ours_length) = ours[i];
+        let (theirs_base, theirs_start, theirs_length) = theirs[j];
+        let start = ours_base.max(theirs_base);
+        let end = (ours_base + ours_length).min(theirs_base + theirs_length);
+        if start < end {
+            regions.push((
+                start,
+                ours_start + start - ours_base,
+                theirs_start + start - theirs_base,
+                end - start,
+            ));
+        }
+        if ours_base + ours_length < theirs_base + theirs_length {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    regions
+}
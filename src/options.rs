@@ -0,0 +1,238 @@
+use crate::sanitize::SanitizeOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Matching strategy used to line up tokens of the two documents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    /// Recursively picks the longest common run of tokens (the default)
+    #[default]
+    LongestMatch,
+    /// Classic longest common subsequence over tokens
+    Lcs,
+    /// Lines up block elements first, then sentences within changed
+    /// blocks, and only compares words between sentences that are similar.
+    /// Rewritten paragraphs show as whole replacements instead of being
+    /// matched on common words.
+    Hierarchical,
+}
+
+/// What is compared when diffing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffMode {
+    /// Markup and text are compared token by token
+    #[default]
+    Markup,
+    /// Only the visible text is compared, so changes to tags and attributes
+    /// are ignored. Scripts and styles are skipped, entities are compared by
+    /// the character they encode and block boundaries count as paragraph
+    /// breaks. Changes are rendered onto the markup of the "after" input.
+    Text,
+}
+
+/// How tokens are grouped before matching, and so the smallest unit a
+/// change can cover
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Granularity {
+    /// Every character of text is a token
+    Character,
+    /// Words, whitespace runs, punctuation, entities and tags are tokens
+    #[default]
+    Word,
+    /// Text up to and including the whitespace after a `.`, `!` or `?` is a
+    /// token. Tags end a sentence early.
+    Sentence,
+    /// Every line of text in the source is a token. Tags are tokens of
+    /// their own and end a line early.
+    Line,
+    /// Everything between two block-level tags, inline markup included, is
+    /// a token
+    Block,
+}
+
+/// How the elements with a given name are diffed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElementStrategy {
+    /// The element is kept whole and shown as an old and a new version when
+    /// anything inside it changes
+    Atomic,
+    /// The element's tags are matched by name only, so it stays in place when
+    /// its attributes change and changes are only marked inside its
+    /// children. The new attributes are kept.
+    DiffChildren,
+    /// The element is kept whole and matched by its start tag only, while
+    /// changed attributes show it as replaced and are reported as
+    /// attribute changes. Changes to its content are ignored: the new
+    /// version is shown, [`HtmlDiff::stats`](crate::HtmlDiff::stats) counts
+    /// it as unchanged and
+    /// [`HtmlDiff::reject_all`](crate::HtmlDiff::reject_all) keeps the new
+    /// content, as the old one isn't part of the diff.
+    DiffAttributesOnly,
+}
+
+/// How whitespace differences are treated when matching tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WhitespaceMode {
+    /// Whitespace must match exactly
+    #[default]
+    Exact,
+    /// Any run of whitespace matches any other run of whitespace
+    Collapse,
+}
+
+/// How character references such as `&amp;` are treated when matching tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityMode {
+    /// Entities must match exactly
+    #[default]
+    Exact,
+    /// Entities are compared by the character they encode, so `&amp;`,
+    /// `&#38;` and `&#x26;` are equal
+    Decode,
+}
+
+/// What to do when a diff exceeds its token, memory, iteration or time limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitFallback {
+    /// Retry with a coarse diff that treats each run of text between tags
+    /// as a single token
+    #[default]
+    Coarse,
+    /// Fail with a [`DiffError`](crate::DiffError)
+    Error,
+}
+
+/// Resource limits for diffing untrusted or very large inputs. All limits
+/// are unset by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Maximum size of either input in bytes. Larger inputs are always
+    /// rejected, regardless of the fallback.
+    pub max_input_bytes: Option<usize>,
+    /// Maximum number of tokens in either input
+    pub max_tokens: Option<usize>,
+    /// Maximum number of token comparisons made while matching
+    pub max_iterations: Option<u64>,
+    /// Maximum wall-clock time in milliseconds. Not enforced on
+    /// `wasm32`, where there is no clock; use `max_iterations` there.
+    pub max_duration_ms: Option<u64>,
+    /// Approximate upper bound on the memory used by tokens, the match
+    /// index and the table of the LCS algorithm, in bytes
+    pub max_memory_bytes: Option<usize>,
+    pub fallback: LimitFallback,
+}
+
+/// Options controlling how [`HtmlDiff`](crate::HtmlDiff) matches and renders changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HtmlDiffOptions {
+    pub algorithm: Algorithm,
+    /// Only applies to rendered diffs; [`HtmlDiff::operations`] always
+    /// compares markup
+    ///
+    /// [`HtmlDiff::operations`]: crate::HtmlDiff::operations
+    pub mode: DiffMode,
+    /// Smallest unit a change can cover, from single characters to whole
+    /// blocks. Text mode compares words when set to `Line` or `Block`.
+    pub granularity: Granularity,
+    /// Element used to mark inserted content
+    pub insert_tag: String,
+    /// Element used to mark deleted content
+    pub delete_tag: String,
+    /// Class added to every insertion marker
+    pub insert_class: Option<String>,
+    /// Class added to every deletion marker
+    pub delete_class: Option<String>,
+    pub whitespace: WhitespaceMode,
+    pub entities: EntityMode,
+    pub limits: Limits,
+    /// Wraps inserted and deleted tags in `<!--diff-ins-->` and
+    /// `<!--diff-del-->` comments, so that
+    /// [`HtmlDiff::reject_all`](crate::HtmlDiff::reject_all) and
+    /// [`HtmlDiff::accept_all`](crate::HtmlDiff::accept_all) can undo
+    /// structural changes exactly
+    pub mark_tag_changes: bool,
+    /// Adds `data-diff-id` and `data-diff-op` attributes to every emitted
+    /// change. Ids count up from 0 in document order, and all segments of
+    /// one change share its id, including both halves of a replacement.
+    pub change_ids: bool,
+    /// Diff strategies by element name, such as `"doc-callout":
+    /// "diff_children"`. Names are matched case-insensitively and the
+    /// strategies take precedence over the
+    /// [`TokenPolicy`](crate::TokenPolicy).
+    pub elements: BTreeMap<String, ElementStrategy>,
+    /// Reduces both inputs to an allowlist of elements and attributes
+    /// before diffing, so that rendered diffs, operations and merges of
+    /// untrusted HTML are safe to display. The byte offsets of
+    /// [`HtmlDiff::operations`] then point into the sanitized inputs.
+    /// [`render_text`] and [`render_ansi`] decode entities, so their output
+    /// is only safe to display as text.
+    ///
+    /// [`HtmlDiff::operations`]: crate::HtmlDiff::operations
+    /// [`render_text`]: crate::render_text
+    /// [`render_ansi`]: crate::render_ansi
+    pub sanitize: Option<SanitizeOptions>,
+}
+
+impl Default for HtmlDiffOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::default(),
+            mode: DiffMode::default(),
+            granularity: Granularity::default(),
+            insert_tag: "ins".to_string(),
+            delete_tag: "del".to_string(),
+            insert_class: None,
+            delete_class: None,
+            whitespace: WhitespaceMode::default(),
+            entities: EntityMode::default(),
+            limits: Limits::default(),
+            mark_tag_changes: false,
+            change_ids: false,
+            elements: BTreeMap::new(),
+            sanitize: None,
+        }
+    }
+}
+
+/// Decodes a character reference token such as `&amp;` or `&#x26;`,
+/// returning `None` for unknown names
+pub(crate) fn decode_entity(entity: &str) -> Option<char> {
+    let name = entity.strip_prefix('&')?.strip_suffix(';')?;
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        "copy" => Some('©'),
+        "reg" => Some('®'),
+        "trade" => Some('™'),
+        "hellip" => Some('…'),
+        "mdash" => Some('—'),
+        "ndash" => Some('–'),
+        "lsquo" => Some('‘'),
+        "rsquo" => Some('’'),
+        "ldquo" => Some('“'),
+        "rdquo" => Some('”'),
+        "euro" => Some('€'),
+        _ => None,
+    }
+}
@@ -0,0 +1,132 @@
+use crate::document::Document;
+use crate::tag::escape_attribute;
+use serde::{Deserialize, Serialize};
+
+/// Colour scheme used by the embedded stylesheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+    /// Follows the viewer's `prefers-color-scheme` setting
+    Auto,
+}
+
+/// Options for rendering a diff as a standalone HTML page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PageOptions {
+    pub title: String,
+    pub theme: Theme,
+    /// Show a legend explaining the change markers above the diff
+    pub legend: bool,
+}
+
+impl Default for PageOptions {
+    fn default() -> Self {
+        Self {
+            title: "HTML diff".to_string(),
+            theme: Theme::default(),
+            legend: true,
+        }
+    }
+}
+
+const LIGHT: &str = "--diff-bg:#ffffff;--diff-fg:#1f2328;--diff-ins-bg:#dafbe1;--diff-ins-fg:#116329;--diff-del-bg:#ffebe9;--diff-del-fg:#a40e26;--diff-attr:#9a6700;--diff-muted:#f6f8fa;";
+const DARK: &str = "--diff-bg:#0d1117;--diff-fg:#e6edf3;--diff-ins-bg:#033a16;--diff-ins-fg:#7ee787;--diff-del-bg:#67060c;--diff-del-fg:#ffa198;--diff-attr:#d29922;--diff-muted:#161b22;";
+
+const STYLES: &str = "\
+body{background:var(--diff-bg);color:var(--diff-fg);font-family:system-ui,sans-serif;line-height:1.5;margin:2rem;}\
+ins{background:var(--diff-ins-bg);color:var(--diff-ins-fg);text-decoration:underline;}\
+del{background:var(--diff-del-bg);color:var(--diff-del-fg);text-decoration:line-through;}\
+ins.diff-ins,del.diff-del{display:inline-block;padding:2px;}\
+ins.diff-ins{outline:2px solid var(--diff-ins-fg);}\
+del.diff-del{outline:2px dashed var(--diff-del-fg);opacity:.6;}\
+a.diff-link{outline:2px dotted var(--diff-attr);}\
+.diff-format{border-bottom:2px dotted var(--diff-attr);}\
+.diff-summary,.diff-legend{background:var(--diff-muted);border-radius:6px;padding:.5rem 1rem;margin-bottom:1rem;}\
+.diff-legend span,.diff-legend ins,.diff-legend del{margin-right:1rem;}";
+
+const CHARSET: &str = "<meta charset=\"utf-8\">";
+
+fn stylesheet(theme: Theme) -> String {
+    let variables = match theme {
+        Theme::Light => format!(":root{{{}}}", LIGHT),
+        Theme::Dark => format!(":root{{{}}}", DARK),
+        Theme::Auto => format!(
+            ":root{{{}}}@media (prefers-color-scheme:dark){{:root{{{}}}}}",
+            LIGHT, DARK
+        ),
+    };
+    format!("<style>{}{}</style>", variables, STYLES)
+}
+
+fn legend() -> &'static str {
+    "<div class=\"diff-legend\"><ins>Inserted</ins><del>Deleted</del>\
+<a class=\"diff-link\">Link changed</a><span class=\"diff-format\">Formatting changed</span></div>"
+}
+
+/// Byte offset just past the `<head>` start tag of lowercased markup
+fn head_start(lower: &str) -> Option<usize> {
+    lower
+        .match_indices("<head")
+        .find(|(i, _)| lower[i + 5..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()))
+        .and_then(|(i, _)| lower[i..].find('>').map(|end| i + end + 1))
+}
+
+/// Wraps rendered diff markup in a complete HTML page with an embedded
+/// stylesheet so it can be opened directly in a browser.
+///
+/// When the diff is itself a full document the stylesheet and legend are
+/// injected into its `<head>` and `<body>` instead.
+pub fn render_page(diff: &str, options: &PageOptions) -> String {
+    let legend = if options.legend { legend() } else { "" };
+
+    if let Some(document) = Document::split(diff) {
+        let style = stylesheet(options.theme);
+        let lower = document.prefix.to_ascii_lowercase();
+        let prefix = match (head_start(&lower), lower.find("</head")) {
+            // The charset goes first in the head, since browsers only look
+            // for it in the first 1024 bytes
+            (Some(head_start), Some(head_end)) if head_start <= head_end => {
+                let charset = if lower[..head_end].contains("charset") {
+                    ""
+                } else {
+                    CHARSET
+                };
+                format!(
+                    "{}{}{}{}{}",
+                    &document.prefix[..head_start],
+                    charset,
+                    &document.prefix[head_start..head_end],
+                    style,
+                    &document.prefix[head_end..]
+                )
+            }
+            // Without a head, one is added just before `<body>` so that it
+            // follows the doctype and `<html>` start tag
+            _ => {
+                let body_start = lower.rfind("<body").unwrap_or(0);
+                format!(
+                    "{}<head>{}{}</head>{}",
+                    &document.prefix[..body_start],
+                    CHARSET,
+                    style,
+                    &document.prefix[body_start..]
+                )
+            }
+        };
+        return format!("{}{}{}{}", prefix, legend, document.body, document.suffix);
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+<title>{}</title>\n{}\n</head>\n<body>\n{}{}\n</body>\n</html>\n",
+        escape_attribute(&options.title),
+        stylesheet(options.theme),
+        legend,
+        diff
+    )
+}
@@ -0,0 +1,274 @@
+use crate::htmldiff::HtmlDiff;
+use crate::tag::{Tag, TagSource};
+
+const INSERTED_TAGS: (&str, &str) = ("<!--diff-ins", "<!--/diff-ins-->");
+const DELETED_TAGS: (&str, &str) = ("<!--diff-del", "<!--/diff-del-->");
+
+/// Comments placed around inserted or deleted tags when
+/// `mark_tag_changes` is enabled, e.g. `<!--diff-ins 3-->` and
+/// `<!--/diff-ins-->` with a change id
+pub(crate) fn tag_markers(insert: bool, id: Option<usize>) -> (String, &'static str) {
+    let (open, close) = if insert { INSERTED_TAGS } else { DELETED_TAGS };
+    match id {
+        Some(id) => (format!("{} {}-->", open, id), close),
+        None => (format!("{}-->", open), close),
+    }
+}
+
+/// Parses an opening tag marker comment into whether it marks inserted tags
+/// and its change id
+fn parse_tag_marker(token: &str) -> Option<(bool, Option<usize>)> {
+    [true, false].into_iter().find_map(|insert| {
+        let (open, _) = if insert { INSERTED_TAGS } else { DELETED_TAGS };
+        let id = token.strip_prefix(open)?.strip_suffix("-->")?.trim();
+        if id.is_empty() {
+            Some((insert, None))
+        } else {
+            id.parse().ok().map(|id| (insert, Some(id)))
+        }
+    })
+}
+
+/// Change id written to a tag by `change_ids`
+fn change_id(tag: &Tag) -> Option<usize> {
+    tag.attribute("data-diff-id")?.parse().ok()
+}
+
+/// Whether changes are kept or undone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Resolution {
+    Accept,
+    Reject,
+}
+
+/// Resolves the changes of rendered diff markup, returning clean HTML when
+/// `target` is `None` or the markup with only change `target` resolved
+pub(crate) fn apply(
+    hd: &HtmlDiff,
+    diff_html: &str,
+    target: Option<usize>,
+    resolution: Resolution,
+) -> String {
+    let mut patcher = Patcher {
+        hd,
+        target,
+        resolution,
+        next_id: 0,
+    };
+    let mut out = String::new();
+    patcher.run(&hd.word_tokens(diff_html), true, &mut out);
+    out
+}
+
+struct Patcher<'a> {
+    hd: &'a HtmlDiff,
+    target: Option<usize>,
+    resolution: Resolution,
+    next_id: usize,
+}
+
+impl Patcher<'_> {
+    /// Identifies the next change by its rendered id, or by its position
+    /// when it has none, returning whether that change is resolved
+    fn next(&mut self, rendered_id: Option<usize>) -> bool {
+        let id = rendered_id.unwrap_or(self.next_id);
+        self.next_id += 1;
+        self.target.is_none_or(|target| target == id)
+    }
+
+    /// Whether an inserted (or deleted) change survives the resolution
+    fn keeps(&self, insert: bool) -> bool {
+        insert == (self.resolution == Resolution::Accept)
+    }
+
+    /// Writes the resolved form of `tokens`. When `emit` is false the tokens
+    /// are being dropped, but changed links inside them are still written
+    /// since they modify an element rather than add one.
+    fn run(&mut self, tokens: &[String], emit: bool, out: &mut String) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+
+            if let Some((insert, rendered_id)) = parse_tag_marker(token) {
+                let close = tag_markers(insert, None).1;
+                let end = find_from(tokens, i + 1, |t| t == close);
+                let content = &tokens[i + 1..end];
+                let resolves = self.next(rendered_id);
+                if !emit {
+                    self.run(content, false, out);
+                } else if resolves {
+                    let keep = self.keeps(insert);
+                    self.run(content, keep, out);
+                } else {
+                    out.push_str(token);
+                    self.run(content, true, out);
+                    out.push_str(closing(tokens, end));
+                }
+                i = end + 1;
+                continue;
+            }
+
+            let Some(tag) = Tag::parse(token).filter(|tag| !tag.closing) else {
+                if emit {
+                    out.push_str(token);
+                }
+                i += 1;
+                continue;
+            };
+
+            if let Some(insert) = self.marker(&tag) {
+                let end = find_from(tokens, i + 1, |t| {
+                    Tag::parse(t).is_some_and(|t| t.closing && t.name == tag.name)
+                });
+                let content = tokens[i + 1..end].concat();
+                let resolves = self.next(change_id(&tag));
+                if emit && !resolves {
+                    out.push_str(token);
+                    out.push_str(&content);
+                    out.push_str(closing(tokens, end));
+                } else if emit && self.keeps(insert) {
+                    out.push_str(&content);
+                }
+                i = end + 1;
+            } else if tag.name == "span" && tag.has_class("diff-format") {
+                let end = matching_end(tokens, i, "span");
+                let resolves = self.next(change_id(&tag));
+                if !emit {
+                    self.run(&tokens[i + 1..end], false, out);
+                } else if resolves {
+                    self.format_span(&tag, &tokens[i + 1..end], out);
+                } else {
+                    out.push_str(token);
+                    self.run(&tokens[i + 1..end], true, out);
+                    out.push_str(closing(tokens, end));
+                }
+                i = end + 1;
+            } else if tag.name == "a" && tag.has_class("diff-link") {
+                if self.next(change_id(&tag)) {
+                    out.push_str(&self.resolve_link(token, &tag));
+                } else {
+                    out.push_str(token);
+                }
+                i += 1;
+            } else if tag.name == "section" && tag.has_class("diff-summary") {
+                // Head changes are reported for review only and are not part
+                // of either document
+                let end = matching_end(tokens, i, "section");
+                if emit && self.target.is_some() {
+                    out.push_str(&tokens[i..end].concat());
+                    out.push_str(closing(tokens, end));
+                }
+                i = end + 1;
+            } else {
+                if emit {
+                    out.push_str(token);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    /// Returns whether a start tag opens an insertion or a deletion marker
+    fn marker(&self, tag: &Tag) -> Option<bool> {
+        let options = self.hd.options();
+        [
+            (true, &options.insert_tag, &options.insert_class),
+            (false, &options.delete_tag, &options.delete_class),
+        ]
+        .into_iter()
+        .find(|(_, name, class)| {
+            tag.name == name.to_ascii_lowercase()
+                && class.as_deref().is_none_or(|class| tag.has_class(class))
+        })
+        .map(|(insert, _, _)| insert)
+    }
+
+    /// Resolves a formatting change given the content of its span. The
+    /// formatting tags that were added sit just inside the span.
+    fn format_span(&mut self, span: &Tag, content: &[String], out: &mut String) {
+        let marker = span.attribute("data-diff-format").unwrap_or("");
+        let removed: Vec<&str> = marker
+            .split_whitespace()
+            .filter_map(|m| m.strip_prefix('-'))
+            .collect();
+        let added = marker
+            .split_whitespace()
+            .filter(|m| m.starts_with('+'))
+            .count();
+        if self.resolution == Resolution::Accept || content.len() < 2 * added {
+            self.run(content, true, out);
+            return;
+        }
+
+        for name in &removed {
+            out.push_str(&format!("<{}>", name));
+        }
+        self.run(&content[added..content.len() - added], true, out);
+        for name in removed.iter().rev() {
+            out.push_str(&format!("</{}>", name));
+        }
+    }
+
+    /// Strips the review markup from a changed link, restoring the old
+    /// link attributes when rejecting. The rest of the tag is left as it
+    /// was written.
+    fn resolve_link(&self, token: &str, tag: &Tag) -> String {
+        let reject = self.resolution == Resolution::Reject;
+        let mut link = TagSource::new(token);
+        for (name, value) in &tag.attributes {
+            if let Some(attribute) = name.strip_prefix("data-diff-old-") {
+                link.remove_attribute(name);
+                if reject {
+                    link.set_attribute(attribute, value);
+                }
+            }
+        }
+        if let Some(added) = tag.attribute("data-diff-added") {
+            link.remove_attribute("data-diff-added");
+            if reject {
+                for attribute in added.split_whitespace() {
+                    link.remove_attribute(attribute);
+                }
+            }
+        }
+        link.remove_attribute("data-diff-id");
+        link.remove_attribute("data-diff-op");
+        link.remove_class("diff-link");
+        link.into_string()
+    }
+}
+
+/// The token closing an element at `end`, if the markup wasn't truncated
+fn closing(tokens: &[String], end: usize) -> &str {
+    tokens.get(end).map_or("", String::as_str)
+}
+
+/// Index of the first token at or after `start` matching `predicate`, or the
+/// end of the tokens
+fn find_from<F>(tokens: &[String], start: usize, predicate: F) -> usize
+where
+    F: Fn(&str) -> bool,
+{
+    (start..tokens.len())
+        .find(|&i| predicate(&tokens[i]))
+        .unwrap_or(tokens.len())
+}
+
+/// Index of the end tag closing the `name` element opened at `start`, or the
+/// end of the tokens
+fn matching_end(tokens: &[String], start: usize, name: &str) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match Tag::parse(token).filter(|tag| tag.name == name) {
+            Some(tag) if tag.closing => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            Some(_) => depth += 1,
+            None => {}
+        }
+    }
+    tokens.len()
+}
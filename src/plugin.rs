@@ -1,42 +1,292 @@
 #[cfg(feature = "extism")]
-use crate::restore_from_diff;
-#[cfg(feature = "extism")]
-use base64;
+use base64::{engine::general_purpose::STANDARD, Engine};
 #[cfg(feature = "extism")]
 use extism_pdk::*;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 
+use crate::encoding::{decode_bytes, encode_string, Charset};
+use crate::error::DiffError;
 use crate::htmldiff::HtmlDiff;
+use crate::options::HtmlDiffOptions;
+use crate::page::{render_page, PageOptions};
+use crate::render::render_json;
+use crate::stats::DiffStats;
+use crate::unified::{try_unified_diff, UnifiedOptions};
+
+/// How documents are carried in the input JSON
+#[cfg(feature = "extism")]
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum InputEncoding {
+    /// Base64 encoded UTF-8
+    #[default]
+    Base64,
+    /// Plain JSON strings
+    Utf8,
+    /// Base64 encoded Latin-1 (Windows-1252) bytes
+    Latin1,
+    /// Base64 encoded UTF-16 bytes
+    Utf16,
+    /// Base64 encoded bytes in a charset detected from a byte order mark or
+    /// `<meta charset>`
+    Auto,
+}
 
+/// How markup is returned from the plugin
 #[cfg(feature = "extism")]
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum OutputEncoding {
+    /// Base64 encoded UTF-8
+    #[default]
+    Base64,
+    /// Plain JSON strings
+    Utf8,
+    /// Base64 encoded Latin-1 (Windows-1252) bytes, with other characters
+    /// written as numeric character references
+    Latin1,
+    /// Base64 encoded UTF-16 bytes, little endian with a byte order mark
+    Utf16,
+}
+
+#[cfg(feature = "extism")]
+#[derive(Deserialize)]
 struct DiffInput {
     before: String,
     after: String,
+    #[serde(default)]
+    encoding: InputEncoding,
+    #[serde(default)]
+    output_encoding: OutputEncoding,
+    #[serde(default)]
+    options: HtmlDiffOptions,
+    /// Wrap the result in a standalone HTML page
+    #[serde(default)]
+    page: Option<PageOptions>,
+    /// Context, mode and labels of `unified_diff`
+    #[serde(default)]
+    unified: UnifiedOptions,
+}
+
+#[cfg(feature = "extism")]
+#[derive(Deserialize)]
+struct RestoreInput {
+    diff: String,
+    #[serde(default)]
+    encoding: InputEncoding,
+    #[serde(default)]
+    output_encoding: OutputEncoding,
+}
+
+/// What `accept_changes` and `reject_changes` resolve
+#[cfg(feature = "extism")]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PatchSource {
+    /// Diff markup, which resolves exactly only when rendered with
+    /// `mark_tag_changes`
+    Diff { diff: String },
+    /// Two documents, diffed with `mark_tag_changes` before resolving
+    Documents { before: String, after: String },
+}
+
+#[cfg(feature = "extism")]
+#[derive(Deserialize)]
+struct PatchInput {
+    #[serde(flatten)]
+    source: PatchSource,
+    /// Resolve only this change instead of all of them
+    #[serde(default)]
+    id: Option<usize>,
+    #[serde(default)]
+    encoding: InputEncoding,
+    #[serde(default)]
+    output_encoding: OutputEncoding,
+    #[serde(default)]
+    options: HtmlDiffOptions,
 }
 
 #[cfg(feature = "extism")]
-#[derive(Serialize, Deserialize)]
-struct DiffOutput {
-    result: String,
+impl DiffInput {
+    /// Parses the input JSON and decodes both documents
+    fn parse(input: &str) -> Result<(Self, String, String), DiffError> {
+        let input: DiffInput = serde_json::from_str(input)?;
+        let before = decode_field("before", &input.before, input.encoding)?;
+        let after = decode_field("after", &input.after, input.encoding)?;
+        Ok((input, before, after))
+    }
 }
 
+#[cfg(feature = "extism")]
+fn decode_field(field: &str, value: &str, encoding: InputEncoding) -> Result<String, DiffError> {
+    let charset = match encoding {
+        InputEncoding::Utf8 => return Ok(value.to_string()),
+        InputEncoding::Base64 => Charset::Utf8,
+        InputEncoding::Latin1 => Charset::Latin1,
+        InputEncoding::Utf16 => Charset::Utf16,
+        InputEncoding::Auto => Charset::Auto,
+    };
+    let bytes = STANDARD
+        .decode(value)
+        .map_err(|e| DiffError::InvalidEncoding {
+            field: field.to_string(),
+            message: e.to_string(),
+        })?;
+    decode_bytes(&bytes, charset, field)
+}
+
+#[cfg(feature = "extism")]
+fn encode_output(output: String, encoding: OutputEncoding) -> String {
+    match encoding {
+        OutputEncoding::Base64 => STANDARD.encode(output),
+        OutputEncoding::Utf8 => output,
+        OutputEncoding::Latin1 => STANDARD.encode(encode_string(&output, Charset::Latin1)),
+        OutputEncoding::Utf16 => STANDARD.encode(encode_string(&output, Charset::Utf16)),
+    }
+}
+
+/// Converts a library error into the plugin error payload
+/// `{"error": {"code", "message", "field"}}`
+#[cfg(feature = "extism")]
+fn respond(result: Result<String, DiffError>) -> FnResult<String> {
+    result.map_err(|err| {
+        let payload = serde_json::json!({
+            "error": {
+                "code": err.code(),
+                "message": err.to_string(),
+                "field": err.field(),
+            }
+        });
+        WithReturnCode::new(Error::msg(payload.to_string()), 1)
+    })
+}
+
+#[cfg(feature = "extism")]
+fn render_diff(input: String) -> Result<String, DiffError> {
+    let (input, before, after) = DiffInput::parse(&input)?;
+
+    let hd = HtmlDiff::with_options(input.options);
+    let mut diff_result = hd.try_diff(&before, &after)?;
+    if let Some(page) = &input.page {
+        diff_result = render_page(&diff_result, page);
+    }
+
+    Ok(encode_output(diff_result, input.output_encoding))
+}
+
+/// Diffs two documents, returning base64 encoded markup by default.
+/// Kept with its original name for existing hosts.
 #[cfg(feature = "extism")]
 #[plugin_fn]
 pub fn DiffHtml(input: String) -> FnResult<String> {
-    // Parse input JSON
-    let input: DiffInput = serde_json::from_str(&input)?;
+    respond(render_diff(input))
+}
 
-    // Decode base64 strings
-    let before = String::from_utf8(base64::decode(&input.before)?)?;
-    let after = String::from_utf8(base64::decode(&input.after)?)?;
+#[cfg(feature = "extism")]
+#[plugin_fn]
+pub fn diff_html(input: String) -> FnResult<String> {
+    respond(render_diff(input))
+}
+
+/// Lists the diff operations as JSON
+#[cfg(feature = "extism")]
+#[plugin_fn]
+pub fn diff_ops(input: String) -> FnResult<String> {
+    respond(DiffInput::parse(&input).and_then(|(input, before, after)| {
+        let hd = HtmlDiff::with_options(input.options);
+        Ok(serde_json::to_string(&hd.try_operations(&before, &after)?)?)
+    }))
+}
+
+/// Returns the diff operations as a versioned [`JsonDiff`](crate::JsonDiff)
+/// document
+#[cfg(feature = "extism")]
+#[plugin_fn]
+pub fn diff_json(input: String) -> FnResult<String> {
+    respond(DiffInput::parse(&input).and_then(|(input, before, after)| {
+        let hd = HtmlDiff::with_options(input.options);
+        Ok(render_json(&hd.try_operations(&before, &after)?))
+    }))
+}
+
+/// Returns word counts of the diff as JSON
+#[cfg(feature = "extism")]
+#[plugin_fn]
+pub fn diff_stats(input: String) -> FnResult<String> {
+    respond(DiffInput::parse(&input).and_then(|(input, before, after)| {
+        let hd = HtmlDiff::with_options(input.options);
+        let stats = DiffStats::from_operations(&hd.try_operations(&before, &after)?);
+        Ok(serde_json::to_string(&stats)?)
+    }))
+}
 
-    let hd = HtmlDiff::new();
-    let diff_result = hd.diff(&before, &after);
+/// Produces a unified diff of the pretty-printed or text-extracted documents
+#[cfg(feature = "extism")]
+#[plugin_fn]
+pub fn unified_diff(input: String) -> FnResult<String> {
+    respond(DiffInput::parse(&input).and_then(|(input, before, after)| {
+        let hd = HtmlDiff::with_options(input.options);
+        Ok(encode_output(
+            try_unified_diff(&hd, &before, &after, &input.unified)?,
+            input.output_encoding,
+        ))
+    }))
+}
 
-    // Encode result as base64
-    let encoded_result = base64::encode(diff_result);
+/// Restores markup from a unified diff
+#[cfg(feature = "extism")]
+#[plugin_fn]
+pub fn restore_from_diff(input: String) -> FnResult<String> {
+    respond(
+        serde_json::from_str::<RestoreInput>(&input)
+            .map_err(DiffError::from)
+            .and_then(|input| {
+                let diff = decode_field("diff", &input.diff, input.encoding)?;
+                Ok(encode_output(
+                    crate::restore_from_diff(&diff),
+                    input.output_encoding,
+                ))
+            }),
+    )
+}
 
-    // Return as base64 encoded string
-    Ok(encoded_result)
+#[cfg(feature = "extism")]
+fn patch(input: String, accept: bool) -> Result<String, DiffError> {
+    let input: PatchInput = serde_json::from_str(&input)?;
+    let hd = HtmlDiff::with_options(HtmlDiffOptions {
+        mark_tag_changes: true,
+        ..input.options
+    });
+    let diff = match &input.source {
+        PatchSource::Diff { diff } => {
+            let diff = decode_field("diff", diff, input.encoding)?;
+            hd.check_size("diff", &diff)?;
+            diff
+        }
+        PatchSource::Documents { before, after } => hd.try_diff(
+            &decode_field("before", before, input.encoding)?,
+            &decode_field("after", after, input.encoding)?,
+        )?,
+    };
+    let patched = match (input.id, accept) {
+        (None, true) => hd.accept_all(&diff),
+        (None, false) => hd.reject_all(&diff),
+        (Some(id), true) => hd.accept(&diff, id),
+        (Some(id), false) => hd.reject(&diff, id),
+    };
+    Ok(encode_output(patched, input.output_encoding))
+}
+
+/// Accepts all changes of rendered diff markup, or only change `id`
+#[cfg(feature = "extism")]
+#[plugin_fn]
+pub fn accept_changes(input: String) -> FnResult<String> {
+    respond(patch(input, true))
+}
+
+/// Rejects all changes of rendered diff markup, or only change `id`
+#[cfg(feature = "extism")]
+#[plugin_fn]
+pub fn reject_changes(input: String) -> FnResult<String> {
+    respond(patch(input, false))
 }
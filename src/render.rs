@@ -0,0 +1,253 @@
+use crate::htmldiff::{ChangeKind, DiffOp};
+use crate::options::decode_entity;
+use crate::tag::element_name;
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// Version of the JSON diff schema, increased whenever a field is renamed,
+/// removed or changes meaning
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// The JSON diff document. For `<div class="container"><p id="p1">one two
+/// three</p></div>` changed to `<div class="container"><p id="p1">one 2
+/// three</p></div>`:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "operations": [
+///     {
+///       "kind": "equal",
+///       "before": { "start": 0, "end": 4 },
+///       "after": { "start": 0, "end": 4 },
+///       "before_bytes": { "start": 0, "end": 38 },
+///       "after_bytes": { "start": 0, "end": 38 },
+///       "before_path": "",
+///       "after_path": "",
+///       "before_text": "<div class=\"container\"><p id=\"p1\">one ",
+///       "after_text": "<div class=\"container\"><p id=\"p1\">one "
+///     },
+///     {
+///       "kind": "replace",
+///       "before": { "start": 4, "end": 5 },
+///       "after": { "start": 4, "end": 5 },
+///       "before_bytes": { "start": 38, "end": 41 },
+///       "after_bytes": { "start": 38, "end": 39 },
+///       "before_path": "div.container>p#p1",
+///       "after_path": "div.container>p#p1",
+///       "before_text": "two",
+///       "after_text": "2"
+///     },
+///     {
+///       "kind": "equal",
+///       "before": { "start": 5, "end": 9 },
+///       "after": { "start": 5, "end": 9 },
+///       "before_bytes": { "start": 41, "end": 57 },
+///       "after_bytes": { "start": 39, "end": 55 },
+///       "before_path": "div.container>p#p1",
+///       "after_path": "div.container>p#p1",
+///       "before_text": " three</p></div>",
+///       "after_text": " three</p></div>"
+///     }
+///   ]
+/// }
+/// ```
+///
+/// `kind` is one of `equal`, `insert`, `delete` or `replace`. Token ranges
+/// index the tokens of each document and byte ranges the original inputs,
+/// both with exclusive ends. Paths list the elements enclosing the start of
+/// the operation, each written as its name followed by `#id` and `.class`
+/// parts; they are empty at the top level of a fragment.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JsonDiff<'a> {
+    /// Always [`JSON_SCHEMA_VERSION`]
+    pub version: u32,
+    pub operations: &'a [DiffOp],
+}
+
+/// Serializes diff operations as a [`JsonDiff`] document
+pub fn render_json(operations: &[DiffOp]) -> String {
+    let document = JsonDiff {
+        version: JSON_SCHEMA_VERSION,
+        operations,
+    };
+    serde_json::to_string(&document).expect("diff operations always serialize")
+}
+
+/// Elements that start a new line in text output
+pub(crate) const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// How a text renderer marks changed text
+struct TextStyle {
+    deleted: (&'static str, &'static str),
+    inserted: (&'static str, &'static str),
+    /// Separator between blocks
+    line_break: &'static str,
+    escape: fn(&str) -> String,
+}
+
+const WORD_DIFF: TextStyle = TextStyle {
+    deleted: ("[-", "-]"),
+    inserted: ("{+", "+}"),
+    line_break: "\n",
+    escape: str::to_string,
+};
+
+const MARKDOWN: TextStyle = TextStyle {
+    deleted: ("~~", "~~"),
+    inserted: ("**", "**"),
+    line_break: "\n\n",
+    escape: escape_markdown,
+};
+
+const ANSI: TextStyle = TextStyle {
+    deleted: ("\x1b[31m", "\x1b[0m"),
+    inserted: ("\x1b[32m", "\x1b[0m"),
+    line_break: "\n",
+    escape: str::to_string,
+};
+
+/// Renders diff operations as plain text with git word-diff style
+/// `[-deleted-]{+inserted+}` markers. Tags are stripped and block elements
+/// start new lines.
+pub fn render_text(operations: &[DiffOp]) -> String {
+    render_styled(operations, &WORD_DIFF)
+}
+
+/// Renders diff operations as Markdown with `~~deleted~~` and
+/// `**inserted**` text and blocks separated by blank lines
+pub fn render_markdown(operations: &[DiffOp]) -> String {
+    render_styled(operations, &MARKDOWN)
+}
+
+/// Renders diff operations as text for a terminal, with deleted text in red
+/// and inserted text in green
+pub fn render_ansi(operations: &[DiffOp]) -> String {
+    render_styled(operations, &ANSI)
+}
+
+fn render_styled(operations: &[DiffOp], style: &TextStyle) -> String {
+    let mut out = String::new();
+    for op in operations {
+        match op.kind {
+            ChangeKind::Equal => out.push_str(&(style.escape)(&text_of(&op.after_text))),
+            ChangeKind::Insert => mark(&op.after_text, style.inserted, style, &mut out),
+            ChangeKind::Delete => mark(&op.before_text, style.deleted, style, &mut out),
+            ChangeKind::Replace => {
+                mark(&op.before_text, style.deleted, style, &mut out);
+                mark(&op.after_text, style.inserted, style, &mut out);
+            }
+        }
+    }
+
+    text_lines(&out).join(style.line_break)
+}
+
+/// Splits text into lines with whitespace collapsed, dropping empty lines
+pub(crate) fn text_lines(text: &str) -> Vec<String> {
+    text.split('\n')
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Writes the text of a changed token run with markers around each line,
+/// keeping surrounding whitespace outside the markers
+fn mark(html: &str, (open, close): (&str, &str), style: &TextStyle, out: &mut String) {
+    for (i, line) in text_of(html).split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let text = line.trim();
+        if text.is_empty() {
+            out.push_str(line);
+            continue;
+        }
+        let start = line.len() - line.trim_start().len();
+        out.push_str(&line[..start]);
+        out.push_str(open);
+        out.push_str(&(style.escape)(text));
+        out.push_str(close);
+        out.push_str(&line[start + text.len()..]);
+    }
+}
+
+/// Strips the tags of a token run, turning block elements into line breaks,
+/// whitespace into single spaces and character references into characters
+pub(crate) fn text_of(html: &str) -> String {
+    static PARTS: OnceLock<Regex> = OnceLock::new();
+    let parts =
+        PARTS.get_or_init(|| Regex::new(r"<[^>]*>|&#?[A-Za-z0-9]+;|\s+|[^<&\s]+|.").unwrap());
+
+    let mut text = String::new();
+    for part in parts.find_iter(html).map(|m| m.as_str()) {
+        if part.starts_with('<') {
+            if element_name(part).is_some_and(|name| BLOCK_ELEMENTS.contains(&name.as_str())) {
+                text.push('\n');
+            }
+        } else if part.starts_with('&') {
+            match decode_entity(part) {
+                Some(c) => text.push(c),
+                None => text.push_str(part),
+            }
+        } else if part.trim().is_empty() {
+            text.push(' ');
+        } else {
+            text.push_str(part);
+        }
+    }
+    text
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            // Decoded text may spell out markup, which Markdown passes through
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '\\' | '*' | '_' | '~' | '`' | '[' | ']' | '(' | ')' | '#' | '|' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,211 @@
+use crate::tag::{self, Tag};
+use crate::tokenizer::{TokenKind, Tokenizer};
+use serde::{Deserialize, Serialize};
+
+/// Elements kept by default: text structure, lists, tables, links and images
+const DEFAULT_TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "article",
+    "aside",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "i",
+    "img",
+    "kbd",
+    "li",
+    "main",
+    "mark",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "section",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "u",
+    "ul",
+];
+
+/// Attributes kept by default on allowed elements
+const DEFAULT_ATTRIBUTES: &[&str] = &[
+    "alt", "cite", "class", "colspan", "datetime", "dir", "height", "href", "lang", "rowspan",
+    "span", "src", "title", "width",
+];
+
+/// URL schemes allowed by default in link and source attributes
+const DEFAULT_URL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Elements dropped together with their content, since it is code, styling
+/// or embedded content rather than text
+const DROPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "template", "noscript", "iframe", "object", "embed", "title", "textarea",
+    "xmp", "noembed", "noframes",
+];
+
+/// Attributes holding a URL, checked against the allowed schemes
+const URL_ATTRIBUTES: &[&str] = &[
+    "href",
+    "src",
+    "cite",
+    "action",
+    "formaction",
+    "poster",
+    "background",
+    "longdesc",
+    "xlink:href",
+];
+
+/// Allowlist applied to both inputs before diffing when
+/// [`HtmlDiffOptions::sanitize`](crate::HtmlDiffOptions::sanitize) is set,
+/// so that the rendered diff is safe to insert with `innerHTML`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SanitizeOptions {
+    /// Elements whose tags are kept. Other tags are dropped and their text
+    /// kept, except for scripts, styles, embedded content and similar
+    /// elements, which are dropped with their content.
+    pub tags: Vec<String>,
+    /// Attributes kept on allowed elements. Event handlers such as
+    /// `onclick` are dropped even when listed.
+    pub attributes: Vec<String>,
+    /// Schemes allowed in URL attributes such as `href` and `src`. Relative
+    /// URLs are always allowed.
+    pub url_schemes: Vec<String>,
+}
+
+impl Default for SanitizeOptions {
+    fn default() -> Self {
+        let owned = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        Self {
+            tags: owned(DEFAULT_TAGS),
+            attributes: owned(DEFAULT_ATTRIBUTES),
+            url_schemes: owned(DEFAULT_URL_SCHEMES),
+        }
+    }
+}
+
+/// Reduces HTML to the allowed elements and attributes. Comments and
+/// doctypes are dropped, kept tags are written out again with quoted and
+/// escaped attribute values, and anything that looks like a tag but isn't
+/// one is escaped as text.
+pub fn sanitize(html: &str, options: &SanitizeOptions) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut tokens = Tokenizer::new(html);
+
+    while let Some(token) = tokens.next() {
+        let text = token.text(html);
+        match token.kind {
+            TokenKind::Comment => {}
+            TokenKind::Tag if text.starts_with("<!") || text.starts_with("<?") => {}
+            TokenKind::Tag => match Tag::parse(text) {
+                Some(tag) if DROPPED_ELEMENTS.contains(&tag.name.as_str()) => {
+                    if !tag.closing && !text.ends_with("/>") {
+                        skip_element(&mut tokens, html, &tag.name);
+                    }
+                }
+                Some(tag) if allowed(&options.tags, &tag.name) => {
+                    out.push_str(&clean_tag(tag, options).render());
+                }
+                Some(_) => {}
+                None => out.push_str(&text.replace('<', "&lt;").replace('>', "&gt;")),
+            },
+            TokenKind::Text | TokenKind::Whitespace | TokenKind::Entity | TokenKind::Element => {
+                out.push_str(text)
+            }
+        }
+    }
+    out
+}
+
+/// Skips the tokens of an element up to and including its end tag
+fn skip_element(tokens: &mut Tokenizer, html: &str, name: &str) {
+    let mut depth = 1;
+    for token in tokens {
+        if token.kind != TokenKind::Tag {
+            continue;
+        }
+        let text = token.text(html);
+        if tag::element_name(text).as_deref() != Some(name) {
+            continue;
+        }
+        if text.starts_with("</") {
+            depth -= 1;
+            if depth == 0 {
+                return;
+            }
+        } else if !text.ends_with("/>") {
+            depth += 1;
+        }
+    }
+}
+
+/// Drops the attributes of a kept tag that aren't allowed, including URLs
+/// with a scheme that isn't allowed
+fn clean_tag(mut tag: Tag, options: &SanitizeOptions) -> Tag {
+    tag.attributes = tag
+        .attributes
+        .into_iter()
+        .filter(|(name, _)| !name.starts_with("on") && allowed(&options.attributes, name))
+        .filter(|(name, value)| {
+            !URL_ATTRIBUTES.contains(&name.as_str()) || safe_url(value, &options.url_schemes)
+        })
+        .collect();
+    tag
+}
+
+fn allowed(names: &[String], name: &str) -> bool {
+    names
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(name))
+}
+
+/// Whether a URL is relative or uses one of `schemes`. Whitespace and
+/// control characters are ignored, as browsers do when reading the scheme.
+fn safe_url(url: &str, schemes: &[String]) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(end) if url[end..].starts_with(':') => allowed(schemes, &url[..end]),
+        _ => true,
+    }
+}
@@ -0,0 +1,159 @@
+use crate::htmldiff::{ChangeKind, DiffOp};
+use crate::render::text_of;
+use crate::tag::Tag;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Summary of how much changed between two documents
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DiffStats {
+    /// Words added, including the "after" side of replacements
+    pub inserted_words: usize,
+    /// Words removed, including the "before" side of replacements
+    pub deleted_words: usize,
+    /// Words on the "before" side of replacements. These are also counted
+    /// in `deleted_words`.
+    pub replaced_words: usize,
+    pub unchanged_words: usize,
+    /// Characters of text, excluding markup, on either side of a change
+    pub characters_changed: usize,
+    /// Number of insertions, deletions and replacements
+    pub changed_blocks: usize,
+    /// Share of text left unchanged, from 0.0 (nothing in common) to 1.0
+    /// (identical text)
+    pub similarity: f64,
+    /// Names of elements whose attributes changed, e.g. `["a", "img"]`
+    pub attribute_changed_tags: Vec<String>,
+}
+
+impl DiffStats {
+    /// Computes statistics from the operations of a diff
+    pub fn from_operations(operations: &[DiffOp]) -> Self {
+        let mut stats = DiffStats::default();
+        let mut unchanged_characters = 0;
+        let mut total_characters = 0;
+        let mut attribute_changed_tags = BTreeSet::new();
+
+        for op in operations {
+            let before_characters = count_characters(&op.before_text);
+            let after_characters = count_characters(&op.after_text);
+            total_characters += before_characters + after_characters;
+
+            match op.kind {
+                ChangeKind::Equal => {
+                    stats.unchanged_words += count_words(&op.after_text);
+                    unchanged_characters += before_characters + after_characters;
+                    continue;
+                }
+                ChangeKind::Insert => stats.inserted_words += count_words(&op.after_text),
+                ChangeKind::Delete => stats.deleted_words += count_words(&op.before_text),
+                ChangeKind::Replace => {
+                    let replaced = count_words(&op.before_text);
+                    stats.replaced_words += replaced;
+                    stats.deleted_words += replaced;
+                    stats.inserted_words += count_words(&op.after_text);
+                    attribute_changed_tags.extend(changed_attribute_tags(op));
+                }
+            }
+            stats.changed_blocks += 1;
+            stats.characters_changed += before_characters + after_characters;
+        }
+
+        stats.similarity = if total_characters == 0 {
+            1.0
+        } else {
+            unchanged_characters as f64 / total_characters as f64
+        };
+        stats.attribute_changed_tags = attribute_changed_tags.into_iter().collect();
+        stats
+    }
+
+    /// Whether nothing changed at all
+    pub fn is_unchanged(&self) -> bool {
+        self.changed_blocks == 0
+    }
+}
+
+impl fmt::Display for DiffStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} inserted, {} deleted, {} replaced, {} unchanged",
+            plural(self.inserted_words, "word"),
+            self.deleted_words,
+            self.replaced_words,
+            self.unchanged_words
+        )?;
+        writeln!(
+            f,
+            "{} changed in {}",
+            plural(self.characters_changed, "character"),
+            plural(self.changed_blocks, "block")
+        )?;
+        write!(f, "similarity {:.1}%", self.similarity * 100.0)?;
+        if !self.attribute_changed_tags.is_empty() {
+            write!(
+                f,
+                "\nattributes changed on: {}",
+                self.attribute_changed_tags.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A count followed by a noun, pluralized unless the count is one
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+fn tags_in(html: &str) -> impl Iterator<Item = Tag> + '_ {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    TAG.get_or_init(|| Regex::new(r"<[^>]+>").unwrap())
+        .find_iter(html)
+        .filter_map(|m| Tag::parse(m.as_str()))
+}
+
+/// Element names whose start tags were replaced by a start tag of the same
+/// element with different attributes
+fn changed_attribute_tags(op: &DiffOp) -> Vec<String> {
+    let before: Vec<Tag> = tags_in(&op.before_text).filter(|t| !t.closing).collect();
+    let after: Vec<Tag> = tags_in(&op.after_text).filter(|t| !t.closing).collect();
+
+    before
+        .iter()
+        .zip(after.iter())
+        .filter(|(b, a)| b.name == a.name && b.attributes != a.attributes)
+        .map(|(b, _)| b.name.clone())
+        .collect()
+}
+
+/// Counts the characters of a token run's text, with entities decoded.
+/// The line breaks `text_of` puts around blocks aren't part of the text.
+fn count_characters(html: &str) -> usize {
+    text_of(html).chars().filter(|&c| c != '\n').count()
+}
+
+/// Counts the words of a token run, ignoring markup
+fn count_words(html: &str) -> usize {
+    let mut words = 0;
+    let mut in_word = false;
+    for c in text_of(html).chars() {
+        if c.is_alphanumeric() {
+            if !in_word {
+                words += 1;
+            }
+            in_word = true;
+        } else {
+            in_word = false;
+        }
+    }
+    words
+}
@@ -0,0 +1,253 @@
+use crate::options::decode_entity;
+use regex::Regex;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// A parsed start or end tag, e.g. `<img src="a.png" alt="A">`. Attribute
+/// values are decoded, and escaped again by [`Tag::render`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Tag {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub closing: bool,
+}
+
+impl Tag {
+    /// Parses a single tag token. Returns `None` for comments, doctypes
+    /// and anything that isn't a tag.
+    pub fn parse(token: &str) -> Option<Tag> {
+        let caps = tag_regex().captures(token.trim())?;
+        let name = caps.get(2)?.as_str().to_ascii_lowercase();
+        let attributes = caps
+            .get(3)
+            .map(|m| parse_attributes(m.as_str()))
+            .unwrap_or_default();
+
+        Some(Tag {
+            name,
+            attributes,
+            closing: caps.get(1).is_some(),
+        })
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attribute("class")
+            .is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+    }
+
+    /// CSS-like selector of the element, e.g. `div.container` or `p#p1`
+    pub fn selector(&self) -> String {
+        let mut selector = self.name.clone();
+        if let Some(id) = self.attribute("id").filter(|id| !id.is_empty()) {
+            selector.push('#');
+            selector.push_str(id);
+        }
+        for class in self.attribute("class").unwrap_or("").split_whitespace() {
+            selector.push('.');
+            selector.push_str(class);
+        }
+        selector
+    }
+
+    /// Renders the tag back to markup with all attribute values quoted
+    pub fn render(&self) -> String {
+        if self.closing {
+            return format!("</{}>", self.name);
+        }
+        let mut rendered = format!("<{}", self.name);
+        for (name, value) in &self.attributes {
+            rendered.push_str(&format!(" {}=\"{}\"", name, escape_attribute(value)));
+        }
+        rendered.push('>');
+        rendered
+    }
+}
+
+/// A start tag edited in place. Attributes that aren't touched keep their
+/// quotes and escaping, so removing what was added gives back the original
+/// text.
+#[derive(Debug, Clone)]
+pub(crate) struct TagSource(String);
+
+/// Where one attribute is written in a tag
+struct AttributeSpan {
+    /// The attribute and the whitespace before it
+    whole: Range<usize>,
+    /// The value, without its quotes
+    value: Option<Range<usize>>,
+    quote: Option<char>,
+}
+
+impl TagSource {
+    pub fn new(token: &str) -> Self {
+        Self(token.to_string())
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<String> {
+        Tag::parse(&self.0)?.attribute(name).map(str::to_string)
+    }
+
+    /// Replaces the value of an attribute in its own quoting style, or
+    /// appends the attribute when it's missing
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        match self.span(name) {
+            Some(AttributeSpan {
+                value: Some(range),
+                quote,
+                ..
+            }) => {
+                let written = match quote {
+                    Some('\'') => escape_attribute(value).replace('\'', "&#39;"),
+                    Some(_) => escape_attribute(value),
+                    None if !value.is_empty()
+                        && !value.contains(|c: char| {
+                            c.is_ascii_whitespace() || "\"'=<>`".contains(c)
+                        }) =>
+                    {
+                        value.replace('&', "&amp;")
+                    }
+                    None => format!("\"{}\"", escape_attribute(value)),
+                };
+                self.0.replace_range(range, &written);
+            }
+            Some(AttributeSpan { whole, .. }) => {
+                let written = format!(" {}=\"{}\"", name, escape_attribute(value));
+                self.0.replace_range(whole, &written);
+            }
+            None => {
+                let end = if self.0.ends_with("/>") {
+                    self.0.len() - 2
+                } else {
+                    self.0.len() - 1
+                };
+                let written = format!(" {}=\"{}\"", name, escape_attribute(value));
+                self.0.insert_str(end, &written);
+            }
+        }
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) {
+        if let Some(span) = self.span(name) {
+            self.0.replace_range(span.whole, "");
+        }
+    }
+
+    /// Appends a class name, keeping any classes already present
+    pub fn add_class(&mut self, class: &str) {
+        let classes = match self.attribute("class") {
+            Some(existing) if !existing.is_empty() => format!("{} {}", existing, class),
+            _ => class.to_string(),
+        };
+        self.set_attribute("class", &classes);
+    }
+
+    /// Removes a class name, dropping the attribute once no classes are left
+    pub fn remove_class(&mut self, class: &str) {
+        let existing = self.attribute("class").unwrap_or_default();
+        let classes: Vec<&str> = existing
+            .split_whitespace()
+            .filter(|c| *c != class)
+            .collect();
+        if classes.is_empty() {
+            self.remove_attribute("class");
+        } else if classes.len() != existing.split_whitespace().count() {
+            self.set_attribute("class", &classes.join(" "));
+        }
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    /// Finds where the first attribute called `name` is written
+    fn span(&self, name: &str) -> Option<AttributeSpan> {
+        let caps = tag_regex().captures(&self.0)?;
+        let source = caps.get(3)?;
+        let offset = source.start();
+        let attribute = attribute_regex()
+            .captures_iter(source.as_str())
+            .find(|caps| caps[1].eq_ignore_ascii_case(name))?;
+        let whole = attribute.get(0)?;
+        let (value, quote) = match (attribute.get(2), attribute.get(3), attribute.get(4)) {
+            (Some(value), _, _) => (Some(value), Some('"')),
+            (_, Some(value), _) => (Some(value), Some('\'')),
+            (_, _, value) => (value, None),
+        };
+        let start = source.as_str()[..whole.start()].trim_end().len();
+        Some(AttributeSpan {
+            whole: offset + start..offset + whole.end(),
+            value: value.map(|value| offset + value.start()..offset + value.end()),
+            quote,
+        })
+    }
+}
+
+/// Returns the lowercase element name of a start or end tag token
+pub(crate) fn element_name(token: &str) -> Option<String> {
+    let rest = token.strip_prefix('<')?;
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    let name: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == ':')
+        .collect();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_ascii_lowercase())
+    }
+}
+
+/// Escapes a value for use inside a double-quoted attribute
+pub(crate) fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn tag_regex() -> &'static Regex {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    TAG.get_or_init(|| Regex::new(r"(?s)^<(/)?([A-Za-z][A-Za-z0-9:-]*)(.*?)/?>$").unwrap())
+}
+
+fn attribute_regex() -> &'static Regex {
+    static ATTR: OnceLock<Regex> = OnceLock::new();
+    ATTR.get_or_init(|| {
+        Regex::new(r#"([^\s=/>"']+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>"']+)))?"#).unwrap()
+    })
+}
+
+fn parse_attributes(source: &str) -> Vec<(String, String)> {
+    attribute_regex()
+        .captures_iter(source)
+        .map(|caps| {
+            let name = caps[1].to_ascii_lowercase();
+            let value = caps
+                .get(2)
+                .or_else(|| caps.get(3))
+                .or_else(|| caps.get(4))
+                .map(|m| decode_entities(m.as_str()))
+                .unwrap_or_default();
+            (name, value)
+        })
+        .collect()
+}
+
+/// Decodes the character references of an attribute value
+fn decode_entities(value: &str) -> String {
+    static ENTITY: OnceLock<Regex> = OnceLock::new();
+    let entity = ENTITY.get_or_init(|| Regex::new(r"&#?[A-Za-z0-9]+;").unwrap());
+    entity
+        .replace_all(value, |caps: &regex::Captures| {
+            decode_entity(&caps[0]).map_or_else(|| caps[0].to_string(), String::from)
+        })
+        .into_owned()
+}
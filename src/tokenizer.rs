@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Range;
+
+/// Media containers whose whole subtree is diffed as a single unit
+pub(crate) const MEDIA_CONTAINERS: &[&str] = &["video", "audio", "iframe", "picture", "object"];
+
+/// Roots of SVG and MathML content, where `<ins>` and `<del>` would not be
+/// valid, so their whole subtree is diffed as a single unit
+pub(crate) const FOREIGN_ELEMENTS: &[&str] = &["svg", "math"];
+
+/// What a [`Token`] contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum TokenKind {
+    /// A start, end or self-closing tag, or a doctype
+    Tag,
+    /// A word, or a punctuation character followed by any word characters
+    Text,
+    /// A run of whitespace
+    Whitespace,
+    /// A character reference such as `&amp;` or `&#38;`
+    Entity,
+    /// An HTML comment, including its `<!--` and `-->` delimiters
+    Comment,
+    /// An element kept whole with its content, such as a `<video>` or an
+    /// atomic element of a [`TokenPolicy`]. Only produced by
+    /// [`HtmlDiff`](crate::HtmlDiff), never by [`Tokenizer`].
+    Element,
+}
+
+/// A token of HTML source, located by its byte range in the input
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+impl Token {
+    /// The source text of the token
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.span.clone()]
+    }
+}
+
+/// State of the tokenizer while reading a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Between tokens
+    Start,
+    /// Inside a tag, until `>`
+    Tag,
+    /// Inside a comment, until `-->`
+    Comment,
+    /// After `&`, reading a reference name until `;`
+    Entity,
+    /// Reading word characters
+    Text,
+    /// Reading whitespace
+    Whitespace,
+}
+
+/// Splits HTML source into [`Token`]s that cover the whole input. The same
+/// tokens are matched by [`HtmlDiff`](crate::HtmlDiff) before grouping.
+///
+/// Tags and comments are read up to their closing `>` or `-->`, or to the
+/// end of the input when unterminated. A `&` not followed by a reference
+/// name and `;` starts a text token instead of an entity.
+#[derive(Debug, Clone)]
+pub struct Tokenizer<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            position: 0,
+        }
+    }
+}
+
+impl Iterator for Tokenizer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let start = self.position;
+        let rest = &self.source[start..];
+        let mut state = State::Start;
+        let mut kind = TokenKind::Text;
+        let mut end = self.source.len();
+
+        for (offset, c) in rest.char_indices() {
+            let at = start + offset;
+            state = match state {
+                State::Start => match c {
+                    '<' if rest.starts_with("<!--") => {
+                        kind = TokenKind::Comment;
+                        State::Comment
+                    }
+                    '<' => {
+                        kind = TokenKind::Tag;
+                        State::Tag
+                    }
+                    '&' => {
+                        kind = TokenKind::Entity;
+                        State::Entity
+                    }
+                    c if c.is_whitespace() => {
+                        kind = TokenKind::Whitespace;
+                        State::Whitespace
+                    }
+                    _ => State::Text,
+                },
+                State::Tag if c == '>' => {
+                    end = at + 1;
+                    break;
+                }
+                State::Comment if c == '>' && self.source[..at].ends_with("--") && offset >= 5 => {
+                    end = at + 1;
+                    break;
+                }
+                State::Tag | State::Comment => state,
+                State::Entity if c == ';' && offset > 1 => {
+                    end = at + 1;
+                    break;
+                }
+                State::Entity if is_word_char(c) => State::Entity,
+                State::Entity => {
+                    // Not a character reference after all
+                    kind = TokenKind::Text;
+                    end = at;
+                    break;
+                }
+                State::Text if is_word_char(c) => State::Text,
+                State::Whitespace if c.is_whitespace() => State::Whitespace,
+                State::Text | State::Whitespace => {
+                    end = at;
+                    break;
+                }
+            };
+        }
+
+        if start == end {
+            return None;
+        }
+        if state == State::Entity && end == self.source.len() && !rest.ends_with(';') {
+            kind = TokenKind::Text;
+        }
+        self.position = end;
+        Some(Token {
+            kind,
+            span: start..end,
+        })
+    }
+}
+
+/// Characters that continue a word: Unicode word characters as matched by
+/// `\w`, which include combining marks and connector punctuation such as
+/// `_`, plus `#` and `@`
+pub(crate) fn is_word_char(c: char) -> bool {
+    regex_syntax::is_word_character(c) || matches!(c, '#' | '@')
+}
+
+/// Decides which elements [`HtmlDiff`](crate::HtmlDiff) keeps whole. An
+/// atomic element is grouped with everything up to its end tag into a
+/// single token, so it is matched as one unit and shown as a whole deletion
+/// and insertion when anything inside it changes. All other elements are
+/// diffed inside.
+pub trait TokenPolicy: fmt::Debug + Send + Sync {
+    /// Whether the element named `name`, in lowercase, is atomic
+    fn is_atomic(&self, name: &str) -> bool;
+}
+
+/// The policy used unless another is set: media containers such as
+/// `<video>`, `<audio>`, `<iframe>`, `<picture>` and `<object>` are atomic,
+/// and so are `<svg>` and `<math>`, whose contents can't hold diff markers.
+/// [`HtmlDiff`](crate::HtmlDiff) keeps `<svg>` and `<math>` atomic under
+/// other policies too.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultTokenPolicy;
+
+impl TokenPolicy for DefaultTokenPolicy {
+    fn is_atomic(&self, name: &str) -> bool {
+        MEDIA_CONTAINERS.contains(&name) || FOREIGN_ELEMENTS.contains(&name)
+    }
+}
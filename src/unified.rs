@@ -0,0 +1,242 @@
+use crate::error::DiffError;
+use crate::htmldiff::{normalize, HtmlDiff, VOID_ELEMENTS};
+use crate::render::{text_lines, text_of, BLOCK_ELEMENTS};
+use crate::tag::element_name;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Document-level elements that get a line of their own when pretty-printing
+const DOCUMENT_ELEMENTS: &[&str] = &[
+    "html", "head", "body", "title", "meta", "link", "script", "style", "base", "thead", "tbody",
+    "tfoot", "caption", "colgroup", "col", "option", "select", "textarea",
+];
+
+/// How documents are turned into lines for a unified diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnifiedMode {
+    /// Markup pretty-printed with block elements on their own lines
+    #[default]
+    Pretty,
+    /// Visible text only, one line per block
+    Text,
+}
+
+/// Options for [`unified_diff`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnifiedOptions {
+    /// Unchanged lines shown around each change
+    pub context: usize,
+    pub mode: UnifiedMode,
+    /// Name of the "before" document in the `---` header
+    pub before_label: String,
+    /// Name of the "after" document in the `+++` header
+    pub after_label: String,
+}
+
+impl Default for UnifiedOptions {
+    fn default() -> Self {
+        Self {
+            context: 3,
+            mode: UnifiedMode::default(),
+            before_label: "before".to_string(),
+            after_label: "after".to_string(),
+        }
+    }
+}
+
+/// Produces a unified diff of two HTML documents with `---`, `+++` and `@@`
+/// headers, or an empty string when both render to the same lines.
+/// [`restore_from_diff`](crate::restore_from_diff) turns the result back
+/// into an inline HTML diff.
+///
+/// Lines are read with the tokenizer of `hd`, after sanitizing if it's
+/// configured, and matched with its algorithm within its [`Limits`]. If the
+/// limits are exceeded, every line is shown as removed and added. Inputs
+/// larger than `max_input_bytes` give an empty string. Use
+/// [`try_unified_diff`] to get the error.
+///
+/// [`Limits`]: crate::Limits
+pub fn unified_diff(hd: &HtmlDiff, before: &str, after: &str, options: &UnifiedOptions) -> String {
+    let Ok((before_lines, after_lines)) = lines(hd, before, after, options.mode) else {
+        return String::new();
+    };
+    let runs = hd
+        .matching_runs(&before_lines, &after_lines)
+        .unwrap_or_default();
+    render(&before_lines, &after_lines, &runs, options)
+}
+
+/// Like [`unified_diff`], but fails instead of falling back when the
+/// configured limits are exceeded
+pub fn try_unified_diff(
+    hd: &HtmlDiff,
+    before: &str,
+    after: &str,
+    options: &UnifiedOptions,
+) -> Result<String, DiffError> {
+    let (before_lines, after_lines) = lines(hd, before, after, options.mode)?;
+    let runs = hd.matching_runs(&before_lines, &after_lines)?;
+    Ok(render(&before_lines, &after_lines, &runs, options))
+}
+
+/// The lines of both documents, within the input size limit
+fn lines(
+    hd: &HtmlDiff,
+    before: &str,
+    after: &str,
+    mode: UnifiedMode,
+) -> Result<(Vec<String>, Vec<String>), DiffError> {
+    let (before, after) = (normalize(before), normalize(after));
+    hd.check_input_size(before, after)?;
+    let to_lines = |html: &str| match mode {
+        UnifiedMode::Pretty => pretty_lines(hd, &hd.sanitized(html)),
+        UnifiedMode::Text => text_lines(&text_of(&hd.sanitized(html))),
+    };
+    Ok((to_lines(before), to_lines(after)))
+}
+
+/// Writes the hunks of a unified diff, given the runs of lines shared by
+/// both documents as `(start_in_before, start_in_after, length)`
+fn render(
+    before_lines: &[String],
+    after_lines: &[String],
+    runs: &[(usize, usize, usize)],
+    options: &UnifiedOptions,
+) -> String {
+    let mut results = Vec::with_capacity(before_lines.len().max(after_lines.len()));
+    let (mut in_before, mut in_after) = (0, 0);
+    let end = (before_lines.len(), after_lines.len(), 0);
+    for &(start_in_before, start_in_after, length) in runs.iter().chain([&end]) {
+        results.extend(
+            before_lines[in_before..start_in_before]
+                .iter()
+                .map(diff::Result::Left),
+        );
+        results.extend(
+            after_lines[in_after..start_in_after]
+                .iter()
+                .map(diff::Result::Right),
+        );
+        results.extend(
+            before_lines[start_in_before..start_in_before + length]
+                .iter()
+                .zip(&after_lines[start_in_after..start_in_after + length])
+                .map(|(before, after)| diff::Result::Both(before, after)),
+        );
+        in_before = start_in_before + length;
+        in_after = start_in_after + length;
+    }
+
+    let changes: Vec<usize> = results
+        .iter()
+        .enumerate()
+        .filter(|(_, result)| !matches!(result, diff::Result::Both(_, _)))
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Line numbers in each document reached before each result
+    let mut before_numbers = Vec::with_capacity(results.len() + 1);
+    let mut after_numbers = Vec::with_capacity(results.len() + 1);
+    let (mut in_before, mut in_after) = (0, 0);
+    for result in &results {
+        before_numbers.push(in_before);
+        after_numbers.push(in_after);
+        match result {
+            diff::Result::Left(_) => in_before += 1,
+            diff::Result::Right(_) => in_after += 1,
+            diff::Result::Both(_, _) => {
+                in_before += 1;
+                in_after += 1;
+            }
+        }
+    }
+    before_numbers.push(in_before);
+    after_numbers.push(in_after);
+
+    let mut out = String::new();
+    // Writing to a String cannot fail
+    let _ = writeln!(out, "--- {}", options.before_label);
+    let _ = writeln!(out, "+++ {}", options.after_label);
+
+    let context = options.context;
+    let mut next = 0;
+    while next < changes.len() {
+        let start = changes[next].saturating_sub(context);
+        let mut end = changes[next] + 1;
+        next += 1;
+        while next < changes.len() && changes[next] - end <= 2 * context {
+            end = changes[next] + 1;
+            next += 1;
+        }
+        let end = (end + context).min(results.len());
+
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(before_numbers[start], before_numbers[end]),
+            hunk_range(after_numbers[start], after_numbers[end])
+        );
+        for result in &results[start..end] {
+            let _ = match result {
+                diff::Result::Both(line, _) => writeln!(out, " {}", line),
+                diff::Result::Left(line) => writeln!(out, "-{}", line),
+                diff::Result::Right(line) => writeln!(out, "+{}", line),
+            };
+        }
+    }
+    out
+}
+
+/// Hunk range in the `start,count` form, where `start` is 1-based and
+/// `,count` is left out for a single line
+fn hunk_range(start: usize, end: usize) -> String {
+    match end - start {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        count => format!("{},{}", start + 1, count),
+    }
+}
+
+/// Pretty-prints markup with block and document-level tags on their own
+/// lines, indented by nesting, and inline content joined on one line
+fn pretty_lines(hd: &HtmlDiff, html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut depth = 0;
+
+    let flush = |line: &mut String, lines: &mut Vec<String>, depth: usize| {
+        let content = line.trim();
+        if !content.is_empty() {
+            lines.push(format!("{}{}", "  ".repeat(depth), content));
+        }
+        line.clear();
+    };
+
+    for token in hd.html_to_tokens(html) {
+        let block = element_name(&token).filter(|name| {
+            BLOCK_ELEMENTS.contains(&name.as_str()) || DOCUMENT_ELEMENTS.contains(&name.as_str())
+        });
+        match block {
+            Some(name) => {
+                flush(&mut line, &mut lines, depth);
+                let closing = token.starts_with("</");
+                if closing {
+                    depth = depth.saturating_sub(1);
+                }
+                lines.push(format!("{}{}", "  ".repeat(depth), token));
+                if !closing && !VOID_ELEMENTS.contains(&name.as_str()) && !token.ends_with("/>") {
+                    depth += 1;
+                }
+            }
+            None if token.trim().is_empty() => line.push(' '),
+            None => line.push_str(&token),
+        }
+    }
+    flush(&mut line, &mut lines, depth);
+    lines
+}
@@ -0,0 +1,181 @@
+use pretty_assertions::assert_eq;
+use std::fs;
+use std::process::Command;
+use tempfile::NamedTempFile;
+
+fn write_temp(content: &str) -> NamedTempFile {
+    let file = NamedTempFile::new().unwrap();
+    fs::write(file.path(), content).unwrap();
+    file
+}
+
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_diff-html-rs"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_cli_prints_diff() {
+    let before = write_temp("<p>Hello World</p>");
+    let after = write_temp("<p>Hello New World</p>");
+    let stdout = run(&[
+        before.path().to_str().unwrap(),
+        after.path().to_str().unwrap(),
+    ]);
+    assert_eq!(stdout, "<p>Hello <ins>New </ins>World</p>\n");
+}
+
+#[test]
+fn test_cli_standalone_page() {
+    let before = write_temp("<p>Hello World</p>");
+    let after = write_temp("<p>Hello New World</p>");
+    let output = NamedTempFile::new().unwrap();
+    run(&[
+        before.path().to_str().unwrap(),
+        after.path().to_str().unwrap(),
+        "--standalone",
+        "--theme",
+        "dark",
+        "--title",
+        "Release notes",
+        "-o",
+        output.path().to_str().unwrap(),
+    ]);
+
+    let page = fs::read_to_string(output.path()).unwrap();
+    assert!(page.starts_with("<!DOCTYPE html>"));
+    assert!(page.contains("<meta charset=\"utf-8\">"));
+    assert!(page.contains("<title>Release notes</title>"));
+    assert!(page.contains("--diff-bg:#0d1117"));
+    assert!(page.contains("<div class=\"diff-legend\">"));
+    assert!(page.contains("<p>Hello <ins>New </ins>World</p>"));
+}
+
+#[test]
+fn test_cli_stat() {
+    let before = write_temp("<p>Hello World</p>");
+    let after = write_temp("<p>Hello New World</p>");
+    let stdout = run(&[
+        before.path().to_str().unwrap(),
+        after.path().to_str().unwrap(),
+        "--stat",
+    ]);
+    assert_eq!(
+        stdout,
+        "1 word inserted, 0 deleted, 0 replaced, 2 unchanged\n\
+4 characters changed in 1 block\n\
+similarity 84.6%\n"
+    );
+}
+
+#[test]
+fn test_cli_json_format() {
+    let before = write_temp("<p>Hello World</p>");
+    let after = write_temp("<p>Hello New World</p>");
+    let stdout = run(&[
+        before.path().to_str().unwrap(),
+        after.path().to_str().unwrap(),
+        "--format",
+        "json",
+    ]);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json["version"], 1);
+    assert_eq!(json["operations"][1]["kind"], "insert");
+    assert_eq!(json["operations"][1]["after_text"], "New ");
+}
+
+#[test]
+fn test_cli_sanitize_formats() {
+    let before = write_temp("<p>Hello World</p>");
+    let after = write_temp(
+        "<p>Hello <img src=x onerror=alert(1)>World &lt;img src=y onerror=alert(2)&gt;</p>",
+    );
+    let formats = [
+        ("html", true),
+        ("json", true),
+        ("markdown", true),
+        ("unified", true),
+        ("text", false),
+        ("ansi", false),
+        ("unified-text", false),
+    ];
+    for (format, markup) in formats {
+        let stdout = run(&[
+            before.path().to_str().unwrap(),
+            after.path().to_str().unwrap(),
+            "--sanitize",
+            "--format",
+            format,
+        ]);
+        assert!(!stdout.contains("alert(1)"), "{}: {}", format, stdout);
+        // Plain text formats decode the escaped markup in the text
+        assert_eq!(
+            stdout.contains("<img src=y"),
+            !markup,
+            "{}: {}",
+            format,
+            stdout
+        );
+    }
+}
+
+#[test]
+fn test_cli_text_only_rejects_other_outputs() {
+    let before = write_temp("<p>Hello World</p>");
+    let after = write_temp("<p>Hello New World</p>");
+    for extra in [&["--format", "json"][..], &["--stat"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_diff-html-rs"))
+            .args([before.path(), after.path()])
+            .arg("--text-only")
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{:?}", extra);
+    }
+}
+
+#[test]
+fn test_cli_text_format() {
+    let before = write_temp("<p>Hello World</p><p>Bye</p>");
+    let after = write_temp("<p>Hello New World</p><p>Bye</p>");
+    let stdout = run(&[
+        before.path().to_str().unwrap(),
+        after.path().to_str().unwrap(),
+        "--format",
+        "text",
+    ]);
+    assert_eq!(stdout, "Hello {+New+} World\nBye\n");
+}
+
+#[test]
+fn test_cli_unified_format() {
+    let before = write_temp("<p>Hello World</p>");
+    let after = write_temp("<p>Hello New World</p>");
+    let stdout = run(&[
+        before.path().to_str().unwrap(),
+        after.path().to_str().unwrap(),
+        "--format",
+        "unified-text",
+    ]);
+    assert!(stdout.contains("@@ -1 +1 @@\n-Hello World\n+Hello New World\n"));
+}
+
+#[test]
+fn test_cli_element_strategy() {
+    let before = write_temp("<doc-callout type=\"info\"><p>Old text</p></doc-callout>");
+    let after = write_temp("<doc-callout type=\"warning\"><p>New text</p></doc-callout>");
+    let stdout = run(&[
+        before.path().to_str().unwrap(),
+        after.path().to_str().unwrap(),
+        "--element",
+        "doc-callout=diff-children",
+    ]);
+    assert_eq!(
+        stdout,
+        "<doc-callout type=\"warning\"><p><del>Old</del><ins>New</ins> text</p></doc-callout>\n"
+    );
+}
@@ -1,7 +1,13 @@
 use diff_html_rs::htmldiff::HtmlDiff;
+use diff_html_rs::{
+    decode_bytes, detect_charset, encode_string, render_ansi, render_json, render_markdown,
+    render_page, render_text, restore_from_diff, restore_from_diff_to_writer, sanitize,
+    try_unified_diff, unified_diff, Algorithm, ChangeKind, Charset, DiffError, DiffMode,
+    ElementStrategy, EntityMode, Granularity, HtmlDiffOptions, LimitFallback, Limits, PageOptions,
+    SanitizeOptions, TokenKind, TokenPolicy, Tokenizer, UnifiedMode, UnifiedOptions,
+    WhitespaceMode, JSON_SCHEMA_VERSION,
+};
 use pretty_assertions::assert_eq;
-use std::fs;
-use tempfile::NamedTempFile;
 
 #[test]
 fn test_simple_text_insertion() {
@@ -90,3 +96,1054 @@ fn test_self_closing_tags() {
 //     );
 //     assert!(result.contains("<p>5 <del><</del><ins>></ins> 10 & 10 <del>></del><ins><</ins> 5</p>"));
 // }
+
+#[test]
+fn test_image_swap_is_marked() {
+    let diff = HtmlDiff::new();
+    let result = diff.compare(
+        "<p>Logo <img src=\"old.png\" alt=\"Old logo\"></p>",
+        "<p>Logo <img src=\"new.png\" alt=\"New logo\"></p>",
+    );
+    assert_eq!(
+        result.html,
+        "<p>Logo <del class=\"diff-del\"><img src=\"old.png\" alt=\"Old logo\"></del><ins class=\"diff-ins\"><img src=\"new.png\" alt=\"New logo\"></ins></p>"
+    );
+    let changed: Vec<&str> = result
+        .attribute_changes
+        .iter()
+        .map(|c| c.attribute.as_str())
+        .collect();
+    assert_eq!(changed, vec!["src", "alt"]);
+    assert_eq!(
+        result.attribute_changes[0].before.as_deref(),
+        Some("old.png")
+    );
+    assert_eq!(
+        result.attribute_changes[0].after.as_deref(),
+        Some("new.png")
+    );
+}
+
+#[test]
+fn test_video_is_diffed_as_one_unit() {
+    let diff = HtmlDiff::new();
+    let result = diff.compare(
+        "<video controls><source src=\"a.mp4\"></video>",
+        "<video controls><source src=\"b.mp4\"></video>",
+    );
+    assert_eq!(
+        result.html,
+        "<del class=\"diff-del\"><video controls><source src=\"a.mp4\"></video></del><ins class=\"diff-ins\"><video controls><source src=\"b.mp4\"></video></ins>"
+    );
+    assert_eq!(result.attribute_changes.len(), 1);
+    assert_eq!(result.attribute_changes[0].element, "source");
+}
+
+#[test]
+fn test_link_target_change_is_marked() {
+    let diff = HtmlDiff::new();
+    let result = diff.compare(
+        "<p>See <a href=\"https://example.com/docs\">the docs</a></p>",
+        "<p>See <a href=\"https://example.net/docs\" target=\"_blank\">the docs</a></p>",
+    );
+    assert_eq!(
+        result.html,
+        "<p>See <a href=\"https://example.net/docs\" target=\"_blank\" data-diff-old-href=\"https://example.com/docs\" data-diff-added=\"target\" class=\"diff-link\">the docs</a></p>"
+    );
+    let changed: Vec<(&str, Option<&str>)> = result
+        .attribute_changes
+        .iter()
+        .map(|c| (c.attribute.as_str(), c.before.as_deref()))
+        .collect();
+    assert_eq!(
+        changed,
+        vec![("href", Some("https://example.com/docs")), ("target", None)]
+    );
+
+    // Escaped attribute values aren't escaped twice
+    let result = diff.compare(
+        "<p><a href=\"/b?x=1&amp;y=2\" title=\"Tom &amp; Jerry\">b</a></p>",
+        "<p><a href=\"/c?x=1&amp;y=2\" title=\"Tom &amp; Jerry\">b</a></p>",
+    );
+    assert_eq!(
+        result.html,
+        "<p><a href=\"/c?x=1&amp;y=2\" title=\"Tom &amp; Jerry\" data-diff-old-href=\"/b?x=1&amp;y=2\" class=\"diff-link\">b</a></p>"
+    );
+    assert_eq!(
+        result.attribute_changes[0].before.as_deref(),
+        Some("/b?x=1&y=2")
+    );
+}
+
+#[test]
+fn test_replaced_anchor_drops_its_end_tag() {
+    let diff = HtmlDiff::new();
+    let result = diff.compare(
+        "<p><a href=\"/x\">a</a> b</p>",
+        "<p>c <a href=\"/y\">d</a></p>",
+    );
+    assert_eq!(
+        result.html,
+        "<p><del>a</del><ins>c </ins><a href=\"/y\" data-diff-old-href=\"/x\" class=\"diff-link\"><ins>d</ins></a><del> b</del></p>"
+    );
+
+    // Anchors whose link attributes are unchanged are left to the plain diff
+    let result = diff.compare(
+        "<p><a href=\"/x\" class=\"a\">old</a></p>",
+        "<p><a href=\"/x\" class=\"b\">new</a></p>",
+    );
+    assert!(!result.html.contains("diff-link"));
+    assert!(result.attribute_changes.is_empty());
+}
+
+#[test]
+fn test_formatting_added() {
+    let diff = HtmlDiff::new();
+    let result = diff.compare(
+        "<p>This is important text</p>",
+        "<p>This is <strong>important</strong> text</p>",
+    );
+    assert_eq!(
+        result.html,
+        "<p>This is <span class=\"diff-format\" data-diff-format=\"+strong\"><strong>important</strong></span> text</p>"
+    );
+    assert_eq!(result.format_changes.len(), 1);
+    assert_eq!(result.format_changes[0].added, vec!["strong"]);
+    assert_eq!(result.format_changes[0].text, "important");
+}
+
+#[test]
+fn test_formatting_removed_and_swapped() {
+    let diff = HtmlDiff::new();
+    let result = diff.diff(
+        "<p>An <em>old</em> and <u>plain</u> word</p>",
+        "<p>An old and <b>plain</b> word</p>",
+    );
+    assert_eq!(
+        result,
+        "<p>An <span class=\"diff-format\" data-diff-format=\"-em\">old</span> and <span class=\"diff-format\" data-diff-format=\"-u +b\"><b>plain</b></span> word</p>"
+    );
+}
+
+#[test]
+fn test_full_document_diffs_body_only() {
+    let diff = HtmlDiff::new();
+    let result = diff.compare(
+        "<!DOCTYPE html><html><head><title>Old title</title><meta name=\"description\" content=\"Old\"></head><body><p>Hello World</p></body></html>",
+        "<!DOCTYPE html><html><head><title>New title</title><meta name=\"description\" content=\"Old\"><script src=\"app.js\"></script></head><body><p>Hello New World</p></body></html>",
+    );
+    assert_eq!(
+        result.html,
+        "<!DOCTYPE html><html><head><title>New title</title><meta name=\"description\" content=\"Old\"><script src=\"app.js\"></script></head><body>\
+<section class=\"diff-summary\"><h2>Document changes</h2><ul>\
+<li><code>title</code> title: <del>Old title</del><ins>New title</ins></li>\
+<li><code>script</code> app.js: <ins>app.js</ins></li>\
+</ul></section>\
+<p>Hello <ins>New </ins>World</p></body></html>"
+    );
+    assert_eq!(result.head_changes.len(), 2);
+    assert_eq!(result.head_changes[1].before, None);
+
+    // Head text is decoded before it is escaped into the summary
+    let result = diff.compare(
+        "<html><head><title>Tom &amp; Jerry</title></head><body></body></html>",
+        "<html><head><title>Tom &amp; Jerry &lt;3</title></head><body></body></html>",
+    );
+    assert!(result
+        .html
+        .contains("<del>Tom &amp; Jerry</del><ins>Tom &amp; Jerry &lt;3</ins>"));
+    assert_eq!(
+        result.head_changes[0].before.as_deref(),
+        Some("Tom & Jerry")
+    );
+}
+
+#[test]
+fn test_standalone_page_injects_into_full_document() {
+    let page = render_page(
+        "<html><head><title>Doc</title></head><body><p><ins>New</ins></p></body></html>",
+        &PageOptions {
+            legend: false,
+            ..Default::default()
+        },
+    );
+    assert!(page.starts_with("<html><head><meta charset=\"utf-8\"><title>Doc</title><style>"));
+    assert!(page.ends_with("</style></head><body><p><ins>New</ins></p></body></html>"));
+}
+
+#[test]
+fn test_standalone_page_adds_missing_head_after_doctype() {
+    let page = render_page(
+        "<!DOCTYPE html><html><body><p><ins>New</ins></p></body></html>",
+        &PageOptions {
+            legend: false,
+            ..Default::default()
+        },
+    );
+    assert!(page.starts_with("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><style>"));
+    assert!(page.ends_with("</style></head><body><p><ins>New</ins></p></body></html>"));
+}
+
+#[test]
+fn test_custom_marker_tags_and_classes() {
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        insert_tag: "span".to_string(),
+        insert_class: Some("added".to_string()),
+        delete_tag: "s".to_string(),
+        ..Default::default()
+    });
+    let result = diff.diff("<p>Old Content</p>", "<p>New Content</p>");
+    assert_eq!(
+        result,
+        "<p><s>Old</s><span class=\"added\">New</span> Content</p>"
+    );
+}
+
+#[test]
+fn test_whitespace_and_entity_modes() {
+    let before = "<p>Fish &amp; chips</p>";
+    let after = "<p>Fish  &#38;\n chips</p>";
+    assert_ne!(HtmlDiff::new().diff(before, after), after);
+
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        whitespace: WhitespaceMode::Collapse,
+        entities: EntityMode::Decode,
+        ..Default::default()
+    });
+    assert_eq!(diff.diff(before, after), after);
+}
+
+#[test]
+fn test_lcs_algorithm() {
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        algorithm: Algorithm::Lcs,
+        ..Default::default()
+    });
+    let result = diff.diff("<p>The quick brown fox</p>", "<p>A fast red fox</p>");
+    assert_eq!(
+        result,
+        "<p><del>The</del><ins>A</ins> <del>quick</del><ins>fast</ins> <del>brown</del><ins>red</ins> fox</p>"
+    );
+}
+
+#[test]
+fn test_hierarchical_algorithm() {
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        algorithm: Algorithm::Hierarchical,
+        ..Default::default()
+    });
+    let result = diff.diff(
+        "<p>The council met on Monday. It was busy.</p>\n<p>The weather in the city was cold.</p>",
+        "<p>The council met on Tuesday. It was busy.</p>\n<p>A bakery won the prize in the region.</p>",
+    );
+    assert_eq!(
+        result,
+        "<p>The council met on <del>Monday</del><ins>Tuesday</ins>. It was busy.</p>\n<p><del>The weather in the city was cold.</del><ins>A bakery won the prize in the region.</ins></p>"
+    );
+}
+
+#[test]
+fn test_operations_and_stats() {
+    let diff = HtmlDiff::new();
+    let ops = diff.operations("<p>Hello World</p>", "<p>Hello New World</p>");
+    let kinds: Vec<ChangeKind> = ops.iter().map(|op| op.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![ChangeKind::Equal, ChangeKind::Insert, ChangeKind::Equal]
+    );
+    assert_eq!(ops[1].after_text, "New ");
+    assert_eq!(ops[1].after, 3..5);
+
+    let stats = diff.stats("<p>Hello World</p>", "<p>Hello New World</p>");
+    assert_eq!(stats.inserted_words, 1);
+    assert_eq!(stats.deleted_words, 0);
+    assert_eq!(stats.unchanged_words, 2);
+}
+
+#[test]
+fn test_error_codes() {
+    let err = DiffError::InvalidEncoding {
+        field: "before".to_string(),
+        message: "invalid byte".to_string(),
+    };
+    assert_eq!(err.code(), "invalid_encoding");
+    assert_eq!(err.field(), Some("before"));
+    assert_eq!(
+        err.to_string(),
+        "invalid encoding in `before`: invalid byte"
+    );
+
+    let err: DiffError = serde_json::from_str::<serde_json::Value>("{")
+        .unwrap_err()
+        .into();
+    assert_eq!(err.code(), "malformed_json");
+    assert_eq!(err.field(), None);
+}
+
+#[test]
+fn test_decode_legacy_charsets() {
+    let windows_1252 = b"<p>Caf\xe9 \x93quoted\x94 \x80</p>";
+    assert_eq!(detect_charset(windows_1252), Charset::Latin1);
+    assert_eq!(
+        decode_bytes(windows_1252, Charset::Auto, "before").unwrap(),
+        "<p>Café “quoted” €</p>"
+    );
+
+    let declared = b"<meta charset=\"utf-8\"><p>Caf\xe9</p>";
+    let err = decode_bytes(declared, Charset::Auto, "after").unwrap_err();
+    assert_eq!(err.field(), Some("after"));
+
+    let utf16: Vec<u8> = [0xFF, 0xFE]
+        .into_iter()
+        .chain("<p>é</p>".encode_utf16().flat_map(u16::to_le_bytes))
+        .collect();
+    assert_eq!(
+        decode_bytes(&utf16, Charset::Auto, "before").unwrap(),
+        "<p>é</p>"
+    );
+
+    // UTF-16 without a byte order mark, in both byte orders
+    let declared = "<meta charset=\"utf-16\"><p>é</p>";
+    let le: Vec<u8> = declared.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let be: Vec<u8> = declared.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    assert_eq!(detect_charset(&le), Charset::Utf16);
+    assert_eq!(
+        decode_bytes(&le, Charset::Auto, "before").unwrap(),
+        declared
+    );
+    assert_eq!(
+        decode_bytes(&be, Charset::Auto, "before").unwrap(),
+        declared
+    );
+}
+
+#[test]
+fn test_encode_charsets() {
+    assert_eq!(
+        encode_string("<p>Café € 日</p>", Charset::Latin1),
+        b"<p>Caf\xe9 \x80 &#26085;</p>"
+    );
+    let utf16 = encode_string("<p>é</p>", Charset::Utf16);
+    assert_eq!(
+        decode_bytes(&utf16, Charset::Auto, "before").unwrap(),
+        "<p>é</p>"
+    );
+}
+
+#[test]
+fn test_input_size_limit() {
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        limits: Limits {
+            max_input_bytes: Some(16),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let err = diff
+        .try_diff("<p>Short</p>", "<p>Much longer content</p>")
+        .unwrap_err();
+    assert_eq!(
+        err,
+        DiffError::SizeLimitExceeded {
+            field: "after".to_string(),
+            limit: 16,
+            actual: 26,
+        }
+    );
+    // Oversized inputs are rejected even when limits fall back
+    assert!(diff
+        .diff("<p>Short</p>", "<p>Much longer content</p>")
+        .is_empty());
+    assert!(diff
+        .operations("<p>Short</p>", "<p>Much longer content</p>")
+        .is_empty());
+}
+
+#[test]
+fn test_limits_fall_back_to_coarse_diff() {
+    let limits = Limits {
+        max_tokens: Some(6),
+        ..Default::default()
+    };
+    let coarse = HtmlDiff::with_options(HtmlDiffOptions {
+        limits,
+        ..Default::default()
+    });
+    assert_eq!(
+        coarse.diff("<p>Hello World</p>", "<p>Hello New World</p>"),
+        "<p><del>Hello World</del><ins>Hello New World</ins></p>"
+    );
+
+    let strict = HtmlDiff::with_options(HtmlDiffOptions {
+        limits: Limits {
+            fallback: LimitFallback::Error,
+            ..limits
+        },
+        ..Default::default()
+    });
+    let err = strict
+        .try_diff("<p>Hello World</p>", "<p>Hello New World</p>")
+        .unwrap_err();
+    assert_eq!(err.code(), "token_limit_exceeded");
+    assert_eq!(
+        err.to_string(),
+        "`after` has 7 tokens, more than the limit of 6"
+    );
+
+    let err = HtmlDiff::with_options(HtmlDiffOptions {
+        limits: Limits {
+            max_memory_bytes: Some(64),
+            fallback: LimitFallback::Error,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .try_diff("<p>Hello World</p>", "<p>Hello New World</p>")
+    .unwrap_err();
+    assert_eq!(err.code(), "memory_limit_exceeded");
+    assert_eq!(err.field(), None);
+
+    // The LCS table counts toward the memory estimate
+    let (before, after) = ("a ".repeat(100), "b ".repeat(100));
+    let options = HtmlDiffOptions {
+        limits: Limits {
+            max_memory_bytes: Some(100_000),
+            fallback: LimitFallback::Error,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(HtmlDiff::with_options(options.clone())
+        .try_diff(&before, &after)
+        .is_ok());
+    let lcs = HtmlDiff::with_options(HtmlDiffOptions {
+        algorithm: Algorithm::Lcs,
+        ..options
+    });
+    assert_eq!(
+        lcs.try_diff(&before, &after).unwrap_err().code(),
+        "memory_limit_exceeded"
+    );
+}
+
+#[test]
+fn test_iteration_budget() {
+    let before = "a ".repeat(200);
+    let after = "a b ".repeat(100);
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        limits: Limits {
+            max_iterations: Some(100),
+            fallback: LimitFallback::Error,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    assert_eq!(diff.try_diff(&before, &after), Err(DiffError::Timeout));
+    assert_eq!(
+        diff.diff(&before, &after),
+        format!("<del>{}</del><ins>{}</ins>", before, after)
+    );
+    let operations = diff.operations(&before, &after);
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0].before, 0..400);
+    assert_eq!(operations[0].after, 0..400);
+
+    // The fallback replaces the body only and keeps markup out of markers
+    let before = format!(
+        "<!DOCTYPE html><html><head></head><body><p>{}</p></body></html>",
+        before
+    );
+    let after = format!(
+        "<!DOCTYPE html><html><head></head><body><p>{}</p></body></html>",
+        after
+    );
+    assert!(diff
+        .diff(&before, &after)
+        .starts_with("<!DOCTYPE html><html><head></head><body><p><del>a a "));
+    assert!(diff
+        .diff(&before, &after)
+        .ends_with(" b </ins></p></body></html>"));
+}
+
+#[test]
+fn test_streaming_matches_string_output() {
+    let diff = HtmlDiff::new();
+    let before = "<p>The quick brown fox</p>";
+    let after = "<p>A fast red fox</p>";
+
+    let mut written = Vec::new();
+    diff.diff_to_writer(before, after, &mut written).unwrap();
+    assert_eq!(
+        String::from_utf8(written).unwrap(),
+        diff.diff(before, after)
+    );
+
+    let mut formatted = String::new();
+    diff.diff_to_fmt(before, after, &mut formatted).unwrap();
+    assert_eq!(formatted, diff.diff(before, after));
+}
+
+#[test]
+fn test_restore_from_diff_to_writer() {
+    let diff_content = " <p>Intro</p>\n-<p>Old line</p>\n+<p>New line</p>\n";
+    let mut written = Vec::new();
+    restore_from_diff_to_writer(diff_content, &mut written).unwrap();
+    let written = String::from_utf8(written).unwrap();
+    assert_eq!(written, restore_from_diff(diff_content));
+    assert_eq!(
+        written,
+        "<p>Intro</p>\n<p><del>Old</del><ins>New</ins> line</p>"
+    );
+}
+
+#[test]
+fn test_stats_summary() {
+    let diff = HtmlDiff::new();
+    let stats = diff.stats(
+        "<p>Hello old World</p><a href=\"/old\">link</a>",
+        "<p>Hi World again</p><a href=\"/new\">link</a>",
+    );
+    assert_eq!(stats.inserted_words, 2);
+    assert_eq!(stats.deleted_words, 2);
+    assert_eq!(stats.replaced_words, 2);
+    assert_eq!(stats.unchanged_words, 2);
+    assert_eq!(stats.changed_blocks, 3);
+    assert_eq!(stats.attribute_changed_tags, vec!["a"]);
+    assert!(stats.similarity > 0.5 && stats.similarity < 1.0);
+
+    let unchanged = diff.stats("<p>Same</p>", "<p>Same</p>");
+    assert!(unchanged.is_unchanged());
+    assert_eq!(unchanged.similarity, 1.0);
+
+    // Entities count as the character they stand for
+    let stats = diff.stats("<p>Tom Jerry</p>", "<p>Tom &amp; Jerry</p>");
+    assert_eq!(stats.characters_changed, 2);
+}
+
+#[test]
+fn test_merge3() {
+    let diff = HtmlDiff::new();
+    let base = "<h1>Title</h1><p>one two three</p>";
+
+    let merged = diff.merge3(
+        base,
+        "<h1>Title</h1><p>one TWO three</p>",
+        "<h1>New title</h1><p>one two three four</p>",
+    );
+    assert!(!merged.has_conflicts);
+    assert_eq!(merged.html, "<h1>New title</h1><p>one TWO three four</p>");
+
+    let conflicted = diff.merge3(
+        base,
+        "<h1>Title</h1><p>one 2 three</p>",
+        "<h1>Title</h1><p>one dos three</p>",
+    );
+    assert!(conflicted.has_conflicts);
+    assert_eq!(conflicted.conflicts, 1);
+    assert_eq!(
+        conflicted.html,
+        "<h1>Title</h1><p>one <span class=\"diff-conflict\">\
+         <span class=\"diff-conflict-ours\">2</span>\
+         <span class=\"diff-conflict-theirs\">dos</span>\
+         </span> three</p>"
+    );
+
+    // Conflicts that change markup grow to whole elements
+    let conflicted = diff.merge3(
+        "<p>one two</p><p>three</p>",
+        "<p>one 2</p><p>three</p>",
+        "<ul><li>one dos</li></ul><p>three</p>",
+    );
+    assert_eq!(conflicted.conflicts, 1);
+    assert_eq!(
+        conflicted.html,
+        "<div class=\"diff-conflict\">\
+         <div class=\"diff-conflict-ours\"><p>one 2</p></div>\
+         <div class=\"diff-conflict-theirs\"><ul><li>one dos</li></ul></div>\
+         </div><p>three</p>"
+    );
+}
+
+#[test]
+fn test_accept_and_reject_round_trip() {
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        mark_tag_changes: true,
+        ..Default::default()
+    });
+    let cases = [
+        (
+            "<p>Hello world</p><p>Second</p>",
+            "<p>Hello there world</p><ul><li>Item</li></ul>",
+        ),
+        ("<p>a <em>b</em> c</p>", "<p>a <strong>b</strong> c</p>"),
+        (
+            "<p><a href=\"/old\">x</a> y</p>",
+            "<p><a href=\"/new\">x</a> z</p>",
+        ),
+        (
+            "<p><img src=\"a.png\"> hi</p>",
+            "<p><img src=\"b.png\"> hi</p>",
+        ),
+        (
+            "<p><a href=\"/b?x=1&amp;y=2\">x</a></p>",
+            "<p><a href=\"/c?x=1&amp;y=2\">x</a></p>",
+        ),
+        (
+            "<p><a href='/old' class='c'>x</a></p>",
+            "<p><a href='/new' class='c' target='_blank'>x</a></p>",
+        ),
+        (
+            "<p><a href=\"\" rel=nofollow>x</a></p>",
+            "<p><a href=\"/new\" rel=noopener>x</a></p>",
+        ),
+    ];
+    for (before, after) in cases {
+        let rendered = diff.diff(before, after);
+        assert_eq!(diff.accept_all(&rendered), after);
+        assert_eq!(diff.reject_all(&rendered), before);
+    }
+}
+
+#[test]
+fn test_accept_single_change() {
+    let diff = HtmlDiff::new();
+    let rendered = diff.diff("<p>one two three</p>", "<p>one 2 three 4</p>");
+    assert_eq!(
+        rendered,
+        "<p>one <del>two</del><ins>2</ins> three<ins> 4</ins></p>"
+    );
+    assert_eq!(
+        diff.accept(&rendered, 1),
+        "<p>one <del>two</del>2 three<ins> 4</ins></p>"
+    );
+    assert_eq!(
+        diff.reject(&rendered, 2),
+        "<p>one <del>two</del><ins>2</ins> three</p>"
+    );
+}
+
+#[test]
+fn test_change_ids() {
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        change_ids: true,
+        ..Default::default()
+    });
+    let rendered = diff.diff("<p>one two three</p>", "<p>one 2 three 4</p>");
+    assert_eq!(
+        rendered,
+        "<p>one <del data-diff-id=\"0\" data-diff-op=\"replace\">two</del>\
+         <ins data-diff-id=\"0\" data-diff-op=\"replace\">2</ins> three\
+         <ins data-diff-id=\"1\" data-diff-op=\"insert\"> 4</ins></p>"
+    );
+    assert_eq!(
+        diff.accept(&rendered, 0),
+        "<p>one 2 three<ins data-diff-id=\"1\" data-diff-op=\"insert\"> 4</ins></p>"
+    );
+
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        change_ids: true,
+        mark_tag_changes: true,
+        ..Default::default()
+    });
+    let (before, after) = (
+        "<p>Hello world</p><p>Second</p><p><a href=\"/old\">link</a></p>",
+        "<p>Hello there world</p><ul><li>Item</li></ul><p><a href=\"/new\">link</a></p>",
+    );
+    let rendered = diff.diff(before, after);
+    assert_eq!(diff.accept_all(&rendered), after);
+    assert_eq!(diff.reject_all(&rendered), before);
+
+    // A formatting change spans two operations but takes a single id
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        change_ids: true,
+        ..Default::default()
+    });
+    let rendered = diff.diff("<p>a <em>b</em> c d</p>", "<p>a <strong>b</strong> c e</p>");
+    assert!(rendered.contains("data-diff-id=\"0\""));
+    assert!(rendered.contains("data-diff-id=\"1\""));
+    assert!(!rendered.contains("data-diff-id=\"2\""));
+}
+
+#[test]
+fn test_json_output() {
+    let diff = HtmlDiff::new();
+    let before =
+        "<html><body><div class=\"container\"><p id=\"p1\">one two three</p></div></body></html>";
+    let after =
+        "<html><body><div class=\"container\"><p id=\"p1\">one 2 three</p></div></body></html>";
+    let operations = diff.operations(before, after);
+    let replace = operations
+        .iter()
+        .find(|op| op.kind == ChangeKind::Replace)
+        .unwrap();
+    assert_eq!(replace.before_path, "body>div.container>p#p1");
+    assert_eq!(&before[replace.before_bytes.clone()], "two");
+    assert_eq!(&after[replace.after_bytes.clone()], "2");
+
+    let json: serde_json::Value = serde_json::from_str(&render_json(&operations)).unwrap();
+    assert_eq!(json["version"], JSON_SCHEMA_VERSION);
+    assert_eq!(json["operations"][1]["kind"], "replace");
+    assert_eq!(
+        json["operations"][1]["after_path"],
+        "body>div.container>p#p1"
+    );
+
+    // The README example is the output for these inputs
+    let readme = include_str!("../README.md");
+    let example = readme
+        .split("```json\n")
+        .find(|block| block.contains("\"version\""))
+        .and_then(|block| block.split("```").next())
+        .unwrap();
+    let operations = diff.operations(
+        "<div class=\"container\"><p id=\"p1\">one two three</p></div>",
+        "<div class=\"container\"><p id=\"p1\">one 2 three</p></div>",
+    );
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(example).unwrap(),
+        serde_json::from_str::<serde_json::Value>(&render_json(&operations)).unwrap()
+    );
+}
+
+#[test]
+fn test_text_renderers() {
+    let diff = HtmlDiff::new();
+    let operations = diff.operations(
+        "<h1>Title</h1><p>Hello old world</p><ul><li>one</li><li>two</li></ul>",
+        "<h1>New Title</h1><p>Hello new world &amp; more</p><ul><li>one</li></ul>",
+    );
+    assert_eq!(
+        render_text(&operations),
+        "{+New+} Title\nHello [-old-]{+new+} world {+& more+}\none\n[-two-]"
+    );
+    assert_eq!(
+        render_markdown(&operations),
+        "**New** Title\n\nHello ~~old~~**new** world **&amp; more**\n\none\n\n~~two~~"
+    );
+    assert_eq!(
+        render_ansi(&operations).lines().nth(1),
+        Some("Hello \x1b[31mold\x1b[0m\x1b[32mnew\x1b[0m world \x1b[32m& more\x1b[0m")
+    );
+
+    // Escaped markup stays text once decoded
+    let escaped = diff.operations(
+        "<p>Say hi</p>",
+        "<p>Say &lt;img src=x onerror=alert(1)&gt; *hi*</p>",
+    );
+    assert_eq!(
+        render_markdown(&escaped),
+        "Say ~~hi~~**&lt;img src=x onerror=alert\\(1\\)&gt; \\*hi\\***"
+    );
+}
+
+#[test]
+fn test_unified_diff() {
+    let before = "<h1>Title</h1><p>Hello old world</p><p>a</p><p>b</p>";
+    let after = "<h1>Title</h1><p>Hello new world</p><p>a</p><p>b</p>";
+    let diff = HtmlDiff::new();
+    let options = UnifiedOptions {
+        context: 1,
+        mode: UnifiedMode::Text,
+        ..Default::default()
+    };
+    let patch = unified_diff(&diff, before, after, &options);
+    assert_eq!(
+        patch,
+        "--- before\n+++ after\n@@ -1,3 +1,3 @@\n Title\n-Hello old world\n+Hello new world\n a\n"
+    );
+    assert_eq!(
+        restore_from_diff(&patch),
+        "Title\nHello <del>old</del><ins>new</ins> world\na"
+    );
+
+    let pretty = unified_diff(&diff, before, after, &UnifiedOptions::default());
+    assert!(pretty.contains("\n <p>\n-  Hello old world\n+  Hello new world\n </p>\n"));
+    assert_eq!(unified_diff(&diff, before, before, &options), "");
+
+    // The options of the diff apply, limits and sanitizing included
+    let limited = HtmlDiff::with_options(HtmlDiffOptions {
+        limits: Limits {
+            max_tokens: Some(2),
+            ..Default::default()
+        },
+        sanitize: Some(SanitizeOptions::default()),
+        ..Default::default()
+    });
+    assert!(matches!(
+        try_unified_diff(&limited, before, after, &options),
+        Err(DiffError::TokenLimitExceeded { .. })
+    ));
+    assert_eq!(
+        unified_diff(
+            &limited,
+            "<p>a</p><p>b</p><p>c</p>",
+            "<p>a</p><p>c</p>",
+            &options
+        ),
+        "--- before\n+++ after\n@@ -1,3 +1,2 @@\n-a\n-b\n-c\n+a\n+c\n"
+    );
+    let sanitized = unified_diff(
+        &limited,
+        "<p>a</p>",
+        "<p>a<img src=x onerror=alert(1)></p>",
+        &UnifiedOptions::default(),
+    );
+    assert!(sanitized.contains("+  a<img src=\"x\">") && !sanitized.contains("onerror"));
+}
+
+#[test]
+fn test_text_mode_ignores_markup() {
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        mode: DiffMode::Text,
+        ..Default::default()
+    });
+    assert_eq!(
+        diff.diff(
+            "<div><p class=\"a\">Hello old world</p></div>",
+            "<section><p class=\"b\"><strong>Hello</strong> new world</p></section>"
+        ),
+        "<section><p class=\"b\"><strong>Hello</strong> <del>old</del><ins>new</ins> world</p></section>"
+    );
+    assert_eq!(
+        diff.diff(
+            "<p>Tom &amp; Jerry</p><script>var a = 1;</script>",
+            "<p>Tom &#38; Jerry</p><script>var a = 2;</script>"
+        ),
+        "<p>Tom &#38; Jerry</p><script>var a = 2;</script>"
+    );
+}
+
+#[test]
+fn test_granularity() {
+    let before = "<p>The cat sat. It was happy!</p>\n<p>Same</p>";
+    let after = "<p>The bat sat. It was happy!</p>\n<p>Same</p>";
+    let with = |granularity| {
+        HtmlDiff::with_options(HtmlDiffOptions {
+            granularity,
+            ..Default::default()
+        })
+        .diff(before, after)
+    };
+
+    assert_eq!(
+        with(Granularity::Character),
+        "<p>The <del>c</del><ins>b</ins>at sat. It was happy!</p>\n<p>Same</p>"
+    );
+    assert_eq!(
+        with(Granularity::Sentence),
+        "<p><del>The cat sat. </del><ins>The bat sat. </ins>It was happy!</p>\n<p>Same</p>"
+    );
+    assert_eq!(
+        with(Granularity::Line),
+        "<p><del>The cat sat. It was happy!</del><ins>The bat sat. It was happy!</ins></p>\n<p>Same</p>"
+    );
+    assert_eq!(
+        with(Granularity::Block),
+        "<p><del>The cat sat. It was happy!</del><ins>The bat sat. It was happy!</ins></p>\n<p>Same</p>"
+    );
+}
+
+#[test]
+fn test_line_and_block_granularity_keep_tags_whole() {
+    let before = "<div class=\"a\">\n<p><em>one</em> two</p>\n<p>three</p>\n</div>";
+    let after = "<div class=\"a\">\n<p><em>one</em> two</p>\n<p>four</p>\n</div>";
+    for granularity in [Granularity::Line, Granularity::Block] {
+        let diff = HtmlDiff::with_options(HtmlDiffOptions {
+            granularity,
+            ..Default::default()
+        });
+        assert_eq!(
+            diff.diff(before, after),
+            "<div class=\"a\">\n<p><em>one</em> two</p>\n<p><del>three</del><ins>four</ins></p>\n</div>"
+        );
+        let operations = diff.operations(before, after);
+        let replace = operations
+            .iter()
+            .find(|op| op.kind == ChangeKind::Replace)
+            .unwrap();
+        assert_eq!(replace.before_path, "div.a>p");
+    }
+}
+
+#[test]
+fn test_tokenizer() {
+    let html = "<p class=\"a\">Tom &amp; Jerry &  co.</p>\n<!-- a > b -->";
+    let tokens: Vec<(TokenKind, &str)> = Tokenizer::new(html)
+        .map(|token| (token.kind, token.text(html)))
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            (TokenKind::Tag, "<p class=\"a\">"),
+            (TokenKind::Text, "Tom"),
+            (TokenKind::Whitespace, " "),
+            (TokenKind::Entity, "&amp;"),
+            (TokenKind::Whitespace, " "),
+            (TokenKind::Text, "Jerry"),
+            (TokenKind::Whitespace, " "),
+            (TokenKind::Text, "&"),
+            (TokenKind::Whitespace, "  "),
+            (TokenKind::Text, "co"),
+            (TokenKind::Text, "."),
+            (TokenKind::Tag, "</p>"),
+            (TokenKind::Whitespace, "\n"),
+            (TokenKind::Comment, "<!-- a > b -->"),
+        ]
+    );
+
+    // Combining marks such as the virama stay inside their word
+    let html = "हिन्दी café";
+    let words: Vec<&str> = Tokenizer::new(html).map(|token| token.text(html)).collect();
+    assert_eq!(words, vec!["हिन्दी", " ", "café"]);
+    let diff = HtmlDiff::new();
+    assert_eq!(
+        diff.diff("<p>हिन्दी text</p>", "<p>हिंदी text</p>"),
+        "<p><del>हिन्दी</del><ins>हिंदी</ins> text</p>"
+    );
+}
+
+#[derive(Debug)]
+struct Widgets;
+
+impl TokenPolicy for Widgets {
+    fn is_atomic(&self, name: &str) -> bool {
+        name == "x-widget"
+    }
+}
+
+#[test]
+fn test_token_policy() {
+    let diff = HtmlDiff::new().with_token_policy(Widgets);
+    assert_eq!(
+        diff.diff(
+            "<p>Hi <x-widget><b>old</b> text</x-widget></p>",
+            "<p>Hi <x-widget><b>new</b> text</x-widget></p>"
+        ),
+        "<p>Hi <del class=\"diff-del\"><x-widget><b>old</b> text</x-widget></del><ins class=\"diff-ins\"><x-widget><b>new</b> text</x-widget></ins></p>"
+    );
+    // Atomic elements of a policy are not media, so their attributes
+    // aren't reported
+    let result = diff.compare(
+        "<p><x-widget size=\"1\">a</x-widget></p>",
+        "<p><x-widget size=\"2\">a</x-widget></p>",
+    );
+    assert!(result.attribute_changes.is_empty());
+
+    // SVG stays atomic under a custom policy
+    assert_eq!(
+        diff.diff(
+            "<p><svg><circle r=\"1\"/></svg></p>",
+            "<p><svg><circle r=\"2\"/></svg></p>"
+        ),
+        "<p><del class=\"diff-del\"><svg><circle r=\"1\"/></svg></del><ins class=\"diff-ins\"><svg><circle r=\"2\"/></svg></ins></p>"
+    );
+
+    let kinds: Vec<TokenKind> = diff
+        .html_to_tokens("<p><x-widget>a b</x-widget> &amp;</p>")
+        .iter()
+        .map(|token| diff.token_kind(token))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Tag,
+            TokenKind::Element,
+            TokenKind::Whitespace,
+            TokenKind::Entity,
+            TokenKind::Tag,
+        ]
+    );
+}
+
+#[test]
+fn test_foreign_content_is_atomic() {
+    let diff = HtmlDiff::new();
+    let result = diff.compare(
+        "<p>Icon <svg><path d=\"M0 0L1 1\"/></svg> and <math><mi>x</mi></math></p>",
+        "<p>Icon <svg><path d=\"M0 0L2 2\"/></svg> and <math><mi>y</mi></math></p>",
+    );
+    assert_eq!(
+        result.html,
+        "<p>Icon <del class=\"diff-del\"><svg><path d=\"M0 0L1 1\"/></svg></del><ins class=\"diff-ins\"><svg><path d=\"M0 0L2 2\"/></svg></ins> and <del class=\"diff-del\"><math><mi>x</mi></math></del><ins class=\"diff-ins\"><math><mi>y</mi></math></ins></p>"
+    );
+    assert_eq!(result.attribute_changes.len(), 1);
+    assert_eq!(result.attribute_changes[0].element, "path");
+    assert_eq!(result.attribute_changes[0].attribute, "d");
+}
+
+#[test]
+fn test_element_strategies() {
+    let mut options = HtmlDiffOptions::default();
+    options
+        .elements
+        .insert("x-chart".to_string(), ElementStrategy::DiffAttributesOnly);
+    options
+        .elements
+        .insert("VIDEO".to_string(), ElementStrategy::DiffChildren);
+    let diff = HtmlDiff::with_options(options);
+
+    let result = diff.compare(
+        "<x-chart data=\"1\"><p>a</p></x-chart><x-chart data=\"1\">b</x-chart>",
+        "<x-chart data=\"1\"><p>changed</p></x-chart><x-chart data=\"2\">b</x-chart>",
+    );
+    assert_eq!(
+        result.html,
+        "<x-chart data=\"1\"><p>changed</p></x-chart><del class=\"diff-del\"><x-chart data=\"1\">b</x-chart></del><ins class=\"diff-ins\"><x-chart data=\"2\">b</x-chart></ins>"
+    );
+    assert_eq!(result.attribute_changes.len(), 1);
+    assert_eq!(result.attribute_changes[0].attribute, "data");
+    // Content changes are hidden, from the stats and from rejection too
+    let (before, after) = (
+        "<x-chart data=\"1\"><p>a</p></x-chart>",
+        "<x-chart data=\"1\"><p>changed</p></x-chart>",
+    );
+    assert_eq!(diff.stats(before, after).similarity, 1.0);
+    assert_eq!(diff.reject_all(&diff.diff(before, after)), after);
+
+    assert_eq!(
+        diff.diff(
+            "<video controls><p>Old fallback</p></video>",
+            "<video><p>New fallback</p></video>"
+        ),
+        "<video><p><del>Old</del><ins>New</ins> fallback</p></video>"
+    );
+}
+
+#[test]
+fn test_sanitize() {
+    let options = SanitizeOptions::default();
+    assert_eq!(
+        sanitize(
+            "<p onclick=\"alert(1)\" class=x>Hi<script>alert(1)</script> <!-- c --><u>there</u></p>",
+            &options
+        ),
+        "<p class=\"x\">Hi <u>there</u></p>"
+    );
+    assert_eq!(
+        sanitize(
+            "<a href=\"jav&#x61;script&#58;alert(1)\">x</a><a href=\"https://a.b/?c=1&amp;d=2\">y</a> a < b",
+            &options
+        ),
+        "<a>x</a><a href=\"https://a.b/?c=1&amp;d=2\">y</a> a &lt; b"
+    );
+
+    let diff = HtmlDiff::with_options(HtmlDiffOptions {
+        sanitize: Some(options),
+        ..Default::default()
+    });
+    assert_eq!(
+        diff.diff(
+            "<p>Hello <img src=x onerror=alert(1)></p>",
+            "<p>Hello <b onmouseover=\"steal()\">World</b><script>evil()</script></p>"
+        ),
+        "<p>Hello <del class=\"diff-del\"><img src=\"x\"></del><b><ins>World</ins></b></p>"
+    );
+    // Operations are sanitized too, with offsets into the sanitized input
+    let ops = diff.operations(
+        "<p>Hi</p>",
+        "<p>Hi <b onmouseover=\"steal()\">there</b></p>",
+    );
+    assert_eq!(ops[1].kind, ChangeKind::Insert);
+    assert_eq!(ops[1].after_text, " <b>there</b>");
+    assert_eq!(ops[1].after_bytes, 5..18);
+}